@@ -5,18 +5,217 @@
 
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
 
-// Thread-local storage for the circuit breaker state
+/// Name of the breaker backing the unnamed single-breaker API
+const DEFAULT_BREAKER: &str = "default";
+
+type Registry = HashMap<String, CircuitBreakerState>;
+
+// Registry of named circuit breakers. By default each thread (each WASM
+// worker, under the threads proposal) has its own registry, so a
+// dependency's health is tracked once per worker.
+//
+// The `shared-state` feature instead keeps one registry behind a `Mutex`
+// in a `static`, so every thread of a threaded (`atomics`) build
+// coordinates on the same breakers. The tradeoffs:
+// - every call takes the lock, which costs a little even uncontended
+// - a contended lock blocks, and the browser main thread can't block, so
+//   heavy use from several threads should stay off the main thread
+// - the `on_transition` callback and `set_time_unit` stay per thread: a
+//   transition is reported on the thread whose call caused it
+#[cfg(not(feature = "shared-state"))]
 thread_local! {
-    static BREAKER: RefCell<CircuitBreakerState> = RefCell::new(CircuitBreakerState::new(5, 60));
+    static BREAKERS: RefCell<Registry> = RefCell::new(default_registry());
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg(feature = "shared-state")]
+static BREAKERS: std::sync::OnceLock<std::sync::Mutex<Registry>> = std::sync::OnceLock::new();
+
+thread_local! {
+    static ON_TRANSITION: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    static TIME_UNIT: Cell<TimeUnit> = const { Cell::new(TimeUnit::Millis) };
+}
+
+fn default_registry() -> Registry {
+    let mut breakers = HashMap::new();
+    breakers.insert(DEFAULT_BREAKER.to_string(), CircuitBreakerState::default());
+    breakers
+}
+
+/// Run `f` with exclusive access to the breaker registry
+#[cfg(not(feature = "shared-state"))]
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    BREAKERS.with(|b| f(&mut b.borrow_mut()))
+}
+
+/// Run `f` with exclusive access to the breaker registry. A panic while
+/// the lock was held doesn't leave the registry unusable.
+#[cfg(feature = "shared-state")]
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    let lock = BREAKERS.get_or_init(|| std::sync::Mutex::new(default_registry()));
+    f(&mut lock.lock().unwrap_or_else(std::sync::PoisonError::into_inner))
+}
+
+/// Errors surfaced to JS by the named-breaker API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerError {
+    /// No breaker has been created under this name
+    UnknownBreaker(String),
+    /// A configuration value was out of range
+    InvalidConfig(String),
+}
+
+impl fmt::Display for BreakerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakerError::UnknownBreaker(name) => write!(f, "unknown circuit breaker: {}", name),
+            BreakerError::InvalidConfig(msg) => write!(f, "invalid breaker config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BreakerError {}
+
+impl From<BreakerError> for JsValue {
+    fn from(err: BreakerError) -> Self {
+        js_sys::Error::new(&err.to_string()).into()
+    }
+}
+
+/// Run `f` against the breaker registered under `name`, then report any
+/// state transitions it caused once the registry borrow is released
+fn with_breaker<R>(
+    name: &str,
+    f: impl FnOnce(&mut CircuitBreakerState) -> R,
+) -> Result<R, BreakerError> {
+    let (result, transitions) = with_registry(|breakers| {
+        let breaker = breakers
+            .get_mut(name)
+            .ok_or_else(|| BreakerError::UnknownBreaker(name.to_string()))?;
+        let result = f(breaker);
+        Ok((result, std::mem::take(&mut breaker.pending_transitions)))
+    })?;
+    notify_transitions(name, &transitions);
+    Ok(result)
+}
+
+/// Invoke the registered transition callback, swallowing anything it throws
+fn notify_transitions(name: &str, transitions: &[Transition]) {
+    if transitions.is_empty() {
+        return;
+    }
+    let Some(callback) = ON_TRANSITION.with(|cb| cb.borrow().clone()) else {
+        return;
+    };
+    for t in transitions {
+        let _ = callback.call4(
+            &JsValue::NULL,
+            &JsValue::from_str(t.from.as_str()),
+            &JsValue::from_str(t.to.as_str()),
+            &JsValue::from(t.at),
+            &JsValue::from_str(name),
+        );
+    }
+}
+
+/// Run `f` against the default breaker, re-creating it with default
+/// settings if the registry was cleared
+fn with_default_breaker<R>(f: impl FnOnce(&mut CircuitBreakerState) -> R) -> R {
+    let (result, transitions) = with_registry(|breakers| {
+        let breaker = breakers.entry(DEFAULT_BREAKER.to_string()).or_default();
+        let result = f(breaker);
+        (result, std::mem::take(&mut breaker.pending_transitions))
+    });
+    notify_transitions(DEFAULT_BREAKER, &transitions);
+    result
+}
+
+/// Defines each exported per-breaker function twice: as written, against
+/// the default breaker, and under the name given by `#[named(...)]` with a
+/// leading `name` argument, against that registered breaker. The first
+/// parameter names the breaker inside the body. The named variant returns
+/// a `Result`, failing with `UnknownBreaker` for an unregistered name; a
+/// body that already returns one is flattened into it.
+macro_rules! per_breaker {
+    () => {};
+    (
+        $(#[doc = $doc:literal])*
+        #[named($named:ident)]
+        #[wasm_bindgen $($bindgen:tt)*]
+        pub fn $fn:ident($b:ident $(, $(#[$param:meta])* $arg:ident: $ty:ty)* $(,)?)
+            -> Result<$ok:ty, $err:ty> $body:block
+        $($rest:tt)*
+    ) => {
+        $(#[doc = $doc])*
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $fn($($(#[$param])* $arg: $ty),*) -> Result<$ok, $err> {
+            with_default_breaker(|$b| $body)
+        }
+
+        #[doc = concat!("`", stringify!($fn), "` for a named breaker")]
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $named(name: &str, $($(#[$param])* $arg: $ty),*) -> Result<$ok, $err> {
+            with_breaker(name, |$b| $body)?
+        }
+
+        per_breaker!($($rest)*);
+    };
+    (
+        $(#[doc = $doc:literal])*
+        #[named($named:ident)]
+        #[wasm_bindgen $($bindgen:tt)*]
+        pub fn $fn:ident($b:ident $(, $(#[$param:meta])* $arg:ident: $ty:ty)* $(,)?)
+            -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $(#[doc = $doc])*
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $fn($($(#[$param])* $arg: $ty),*) -> $ret {
+            with_default_breaker(|$b| $body)
+        }
+
+        #[doc = concat!("`", stringify!($fn), "` for a named breaker")]
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $named(name: &str, $($(#[$param])* $arg: $ty),*) -> Result<$ret, BreakerError> {
+            with_breaker(name, |$b| $body)
+        }
+
+        per_breaker!($($rest)*);
+    };
+    (
+        $(#[doc = $doc:literal])*
+        #[named($named:ident)]
+        #[wasm_bindgen $($bindgen:tt)*]
+        pub fn $fn:ident($b:ident $(, $(#[$param:meta])* $arg:ident: $ty:ty)* $(,)?) $body:block
+        $($rest:tt)*
+    ) => {
+        $(#[doc = $doc])*
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $fn($($(#[$param])* $arg: $ty),*) {
+            with_default_breaker(|$b| $body)
+        }
+
+        #[doc = concat!("`", stringify!($fn), "` for a named breaker")]
+        #[wasm_bindgen $($bindgen)*]
+        pub fn $named(name: &str, $($(#[$param])* $arg: $ty),*) -> Result<(), BreakerError> {
+            with_breaker(name, |$b| $body)
+        }
+
+        per_breaker!($($rest)*);
+    };
+}
+
+/// Circuit breaker state, exported to JS as an enum. The discriminants are
+/// the stable codes of `to_code`; JSON keeps the variant names.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BreakerState {
-    Closed,
-    Open,
-    HalfOpen,
+    Closed = 0,
+    HalfOpen = 1,
+    Open = 2,
 }
 
 impl BreakerState {
@@ -27,170 +226,6831 @@ impl BreakerState {
             BreakerState::HalfOpen => "half_open",
         }
     }
+
+    /// Stable numeric code for binary protocols and the Prometheus state
+    /// gauge: Closed = 0, HalfOpen = 1, Open = 2
+    pub fn to_code(self) -> u8 {
+        self as u8
+    }
+
+    /// The state with the given `to_code` code, if any
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(BreakerState::Closed),
+            1 => Some(BreakerState::HalfOpen),
+            2 => Some(BreakerState::Open),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome window size used outside `TripMode::Rate`
+const DEFAULT_OUTCOME_WINDOW: u32 = 100;
+/// Successful-call latencies kept for `latency_mean` and percentiles
+const LATENCY_SAMPLES: usize = 100;
+/// Most metadata keys `set_metadata` accepts per breaker
+const MAX_METADATA_KEYS: usize = 32;
+
+/// Token returned by `guard_begin` when the request is denied
+const GUARD_DENIED: u32 = 0;
+
+/// Outstanding guard tokens kept before the oldest are forgotten
+const MAX_OUTSTANDING_GUARDS: usize = 1024;
+
+/// Transitions kept for `drain_events` unless resized
+const DEFAULT_EVENT_LOG_SIZE: u32 = 32;
+
+/// Trip times kept for `recent_trips` unless resized
+const DEFAULT_TRIP_HISTORY_SIZE: u32 = 16;
+
+/// How far behind the last failure a failure timestamp may be and still
+/// count, unless changed with `set_clock_tolerance`
+const DEFAULT_CLOCK_TOLERANCE_MS: u64 = 1000;
+
+/// Entries the failure and call windows keep, however long the window,
+/// unless changed with `set_max_window_entries`
+const DEFAULT_MAX_WINDOW_ENTRIES: u32 = 10_000;
+
+/// Fallback xorshift seed, since an all-zero state never advances
+const DEFAULT_RNG_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// How the Closed state decides to trip
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum TripMode {
+    /// Trip once `failure_score` reaches `failure_threshold`
+    Count,
+    /// Trip once the failure percentage over the last `minimum_calls`
+    /// outcomes reaches `failure_rate_percent`
+    Rate {
+        failure_rate_percent: u32,
+        minimum_calls: u32,
+    },
+    /// Trip once failures in the sliding window exceed
+    /// `k * sqrt(calls in the window)`, with `k` in thousandths
+    Adaptive { k_milli: u32 },
+    /// Trip once `failure_threshold` of the last `window_size` outcomes
+    /// failed, however long ago they were recorded
+    CountWindow { window_size: u32 },
+}
+
+/// How the HalfOpen state decides to close
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum CloseMode {
+    /// Close after `half_open_success_threshold` successes in a row; the
+    /// first failure re-opens
+    Consecutive,
+    /// Collect exactly `half_open_max_calls` probe results, then close if
+    /// at least `half_open_success_threshold` succeeded, else re-open
+    Quorum,
+}
+
+/// Which lifetime counter a failure is accounted under; both trip alike
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FailureKind {
+    Error,
+    Timeout,
+}
+
+/// Why the breaker last changed state, reported as `last_transition_cause`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum TransitionCause {
+    /// Closed to Open: failures, slow calls or a fatal failure
+    FailureThreshold,
+    /// HalfOpen back to Open after failed probes
+    HalfOpenFailure,
+    /// `force_open` or `force_half_open`
+    ManualForce,
+    /// Open to HalfOpen once the recovery timeout passed
+    RecoveryTimeout,
+    /// Open to HalfOpen through `confirm_recovery`
+    RecoveryConfirmed,
+    /// Open to HalfOpen on a late success (`set_recover_on_open_success`)
+    LateSuccess,
+    /// HalfOpen to Closed once the probes succeeded
+    ProbesSucceeded,
+    /// Back to Closed through `reset_breaker` or an `init_*` call
+    Reset,
+    /// Straight to Closed through `force_close`
+    ManualClose,
+    /// Started in HalfOpen by `init_breaker_cold_start`
+    ColdStart,
+}
+
+impl TransitionCause {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransitionCause::FailureThreshold => "failure_threshold",
+            TransitionCause::HalfOpenFailure => "half_open_failure",
+            TransitionCause::ManualForce => "manual_force",
+            TransitionCause::RecoveryTimeout => "recovery_timeout",
+            TransitionCause::RecoveryConfirmed => "recovery_confirmed",
+            TransitionCause::LateSuccess => "late_success",
+            TransitionCause::ProbesSucceeded => "probes_succeeded",
+            TransitionCause::Reset => "reset",
+            TransitionCause::ManualClose => "manual_close",
+            TransitionCause::ColdStart => "cold_start",
+        }
+    }
+
+    /// The cause of an automatic move from `from` to `to`; manual moves
+    /// override it afterwards
+    fn of(from: BreakerState, to: BreakerState) -> Self {
+        match (from, to) {
+            (BreakerState::Closed, _) => TransitionCause::FailureThreshold,
+            (BreakerState::HalfOpen, BreakerState::Open) => TransitionCause::HalfOpenFailure,
+            (BreakerState::HalfOpen, _) => TransitionCause::ProbesSucceeded,
+            (BreakerState::Open, BreakerState::HalfOpen) => TransitionCause::RecoveryTimeout,
+            (BreakerState::Open, _) => TransitionCause::Reset,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Outcome {
+    Success,
+    /// Succeeded, but took at least `slow_call_threshold_ms`
+    SlowSuccess,
+    Failure,
+}
+
+/// Why `allow_request` admitted or denied a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Decision {
+    Closed,
+    /// Denied by the slow-start ramp after recovering
+    SlowStartThrottled,
+    OpenRejected,
+    /// Open past `max_open_duration_ms`; only a reset reopens traffic
+    OpenLatched,
+    /// Opened by `force_open`; waits for a reset or `release_force_open`
+    OpenForced,
+    /// Gated recovery: the timeout has passed, waiting on `confirm_recovery`
+    OpenAwaitingConfirmation,
+    HalfOpenProbe,
+    HalfOpenBudgetExhausted,
+    /// Single-flight: the previous probe hasn't reported yet
+    HalfOpenProbeInFlight,
+    /// Denied because `probe_interval_ms` hasn't passed since the last probe
+    HalfOpenProbeSpacing,
+    /// Denied by the HalfOpen traffic ramp
+    HalfOpenThrottled,
+    /// Denied by `set_shed_percent` load shedding while Closed
+    Shed,
+    /// Let through by `set_enabled(false)` without consulting the state
+    Disabled,
+}
+
+impl Decision {
+    fn as_str(self) -> &'static str {
+        match self {
+            Decision::Closed => "closed",
+            Decision::SlowStartThrottled => "slow_start_throttled",
+            Decision::OpenRejected => "open_rejected",
+            Decision::OpenLatched => "open_latched",
+            Decision::OpenForced => "open_forced",
+            Decision::OpenAwaitingConfirmation => "open_awaiting_confirmation",
+            Decision::HalfOpenProbe => "half_open_probe",
+            Decision::HalfOpenBudgetExhausted => "half_open_budget_exhausted",
+            Decision::HalfOpenProbeInFlight => "half_open_probe_in_flight",
+            Decision::HalfOpenProbeSpacing => "half_open_probe_spacing",
+            Decision::HalfOpenThrottled => "half_open_throttled",
+            Decision::Shed => "shed",
+            Decision::Disabled => "disabled",
+        }
+    }
+
+    fn allowed(self) -> bool {
+        matches!(self, Decision::Closed | Decision::HalfOpenProbe | Decision::Disabled)
+    }
+}
+
+/// A logged transition, as returned by `drain_events`
+#[derive(Serialize)]
+struct Event {
+    from: &'static str,
+    to: &'static str,
+    timestamp: u64,
+}
+
+/// A transition returned by `flush`, tagged with its breaker
+#[derive(Serialize)]
+struct FlushedEvent {
+    name: String,
+    from: &'static str,
+    to: &'static str,
+    timestamp: u64,
+}
+
+/// Outcome of `evaluate_request`
+#[derive(Serialize)]
+struct Evaluation {
+    allowed: bool,
+    state: &'static str,
+    reason: &'static str,
+}
+
+/// Structured snapshot of a breaker, returned to JS by `get_status_obj`
+#[derive(Serialize)]
+struct Status {
+    state: &'static str,
+    failure_count: u32,
+    success_count: u32,
+    failure_threshold: u32,
+    /// `recovery_timeout_ms` in seconds, the unit `init_breaker` takes
+    recovery_timeout: f64,
+    recovery_timeout_ms: u64,
+    last_failure_time: Option<u64>,
+    trip_count: u64,
+    rejected_count: u64,
+    state_changed_at: Option<u64>,
+    last_transition_cause: Option<&'static str>,
+    ignored_failures: u64,
+    shadow_mode: bool,
+    short_circuited_count: u64,
+    evaluations_total: u64,
+    latched: bool,
+    forced_open: bool,
+    failure_score: f64,
+    enabled: bool,
+    /// HalfOpen probes currently in flight
+    half_open_calls: u32,
+    /// Most probes in flight at once, not per HalfOpen window
+    half_open_max: u32,
+    timeout_count: u64,
+    error_count: u64,
+    metadata: BTreeMap<String, String>,
+    quiesced: bool,
+    window_truncated: bool,
+}
+
+/// `get_status` JSON: the `Status` fields plus the `failures` and
+/// `successes` keys it has always reported
+#[derive(Serialize)]
+struct StatusJson<'a> {
+    failures: u32,
+    successes: u32,
+    #[serde(flatten)]
+    status: &'a Status,
+}
+
+/// Convert `value` to a plain JS object, with maps as objects and `None`
+/// as null, just as `JSON.parse` of its JSON would give
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// One entry of `evaluate_many`; unknown breakers have a null `state`
+/// and an `error`
+#[derive(Serialize)]
+struct Candidate {
+    name: String,
+    allowed: bool,
+    state: Option<&'static str>,
+    error: Option<String>,
+}
+
+/// Cumulative time spent in each state, returned by `dwell_times`
+#[derive(Serialize)]
+struct DwellTimes {
+    closed_ms: u64,
+    open_ms: u64,
+    half_open_ms: u64,
+}
+
+/// Static configuration returned by `get_config`; all times in milliseconds
+#[derive(Serialize)]
+struct Config {
+    failure_threshold: u32,
+    recovery_timeout_ms: u64,
+    half_open_max_calls: u32,
+    half_open_success_threshold: u32,
+    window_ms: u64,
+    /// "count", "rate", "adaptive" or "count_window"; settings of the
+    /// other modes are null
+    trip_mode: &'static str,
+    failure_rate_percent: Option<u32>,
+    rate_minimum_calls: Option<u32>,
+    adaptive_k: Option<f64>,
+    count_window_size: Option<u32>,
+    minimum_calls: u32,
+    max_recovery_timeout_ms: u64,
+    jitter_percent: u32,
+    slow_call_threshold_ms: u64,
+    slow_call_rate_percent: u32,
+    ramp_ms: u64,
+    probe_interval_ms: u64,
+    max_open_duration_ms: u64,
+    decay_on_success: bool,
+    shadow_mode: bool,
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const STATUS_TS: &'static str = r#"
+export interface BreakerStatus {
+    state: "closed" | "open" | "half_open";
+    failure_count: number;
+    success_count: number;
+    failure_threshold: number;
+    recovery_timeout: number;
+    recovery_timeout_ms: number;
+    last_failure_time: number | null;
+    trip_count: number;
+    rejected_count: number;
+    state_changed_at: number | null;
+    last_transition_cause:
+        | "failure_threshold"
+        | "half_open_failure"
+        | "manual_force"
+        | "recovery_timeout"
+        | "recovery_confirmed"
+        | "late_success"
+        | "probes_succeeded"
+        | "reset"
+        | "manual_close"
+        | "cold_start"
+        | null;
+    ignored_failures: number;
+    shadow_mode: boolean;
+    short_circuited_count: number;
+    evaluations_total: number;
+    latched: boolean;
+    forced_open: boolean;
+    failure_score: number;
+    enabled: boolean;
+    /** HalfOpen probes currently in flight */
+    half_open_calls: number;
+    /** Most probes in flight at once, not per HalfOpen window */
+    half_open_max: number;
+    timeout_count: number;
+    error_count: number;
+    metadata: Record<string, string>;
+    quiesced: boolean;
+    window_truncated: boolean;
 }
 
+export interface RequestDecision {
+    allowed: boolean;
+    state: "closed" | "open" | "half_open";
+    reason:
+        | "closed"
+        | "slow_start_throttled"
+        | "open_rejected"
+        | "open_latched"
+        | "open_forced"
+        | "open_awaiting_confirmation"
+        | "half_open_probe"
+        | "half_open_budget_exhausted"
+        | "half_open_probe_in_flight"
+        | "half_open_probe_spacing"
+        | "half_open_throttled"
+        | "shed"
+        | "disabled";
+}
+"#;
+
+/// A state change waiting to be reported to the transition callback
+#[derive(Clone, Copy)]
+struct Transition {
+    from: BreakerState,
+    to: BreakerState,
+    at: u64,
+}
+
+/// Full breaker state; everything except queued events survives export/import
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 struct CircuitBreakerState {
     state: BreakerState,
     failure_count: u32,
+    /// Successes in the current state's window: while Closed, those among
+    /// the last `outcome_window()` outcomes; while HalfOpen, the unbroken
+    /// run of probe successes; always 0 while Open
     success_count: u32,
     failure_threshold: u32,
-    recovery_timeout: u64,
+    recovery_timeout_ms: u64,
     last_failure_time: Option<u64>,
     half_open_calls: u32,
-    half_open_max: u32,
+    /// Probes allowed in flight at once while HalfOpen
+    half_open_max_calls: u32,
+    /// Successes required while HalfOpen before closing
+    half_open_success_threshold: u32,
+    /// Sliding window for counting failures, 0 disables it
+    window_ms: u64,
+    /// Timestamp and weight (in thousandths) of failures inside the window,
+    /// keeping only the newest ones that still reach `failure_threshold`
+    failure_times: VecDeque<(u64, u64)>,
+    /// Weighted failures in thousandths; each plain failure counts 1000.
+    /// Tracks `failure_count`, which stays the number of failures.
+    failure_score: u64,
+    trip_mode: TripMode,
+    close_mode: CloseMode,
+    /// Failed probes in the current quorum window
+    probe_failures: u32,
+    /// Most recent Closed-state outcomes, for rate, slow-call, and health checks
+    outcomes: VecDeque<Outcome>,
+    /// Upper bound for the backed-off recovery timeout, 0 disables backoff
+    max_recovery_timeout_ms: u64,
+    /// Failed HalfOpen probes since the breaker last closed
+    consecutive_trips: u32,
+    /// Maximum random extension of the recovery timeout, as a percentage
+    jitter_percent: u32,
+    /// Extension drawn for the current Open period
+    jitter_ms: u64,
+    /// xorshift64 state used to draw jitter
+    rng_state: u64,
+    /// Latest timestamp seen, used to stamp transitions from untimed calls
+    clock_ms: u64,
+    #[serde(skip)]
+    pending_transitions: Vec<Transition>,
+    /// Recent transitions for `drain_events`, oldest dropped first
+    #[serde(skip)]
+    event_log: VecDeque<Transition>,
+    event_log_size: u32,
+    /// Latency at which a successful call counts as slow, 0 disables it
+    slow_call_threshold_ms: u64,
+    /// Share of slow calls in the outcome window that trips the breaker
+    slow_call_rate_percent: u32,
+    /// Calls required since closing before `TripMode::Count` may trip
+    minimum_calls: u32,
+    /// Successes and failures recorded since the breaker last closed
+    calls_since_closed: u32,
+    /// Tokens handed out by `guard_begin` in the current state generation
+    outstanding_guards: VecDeque<u32>,
+    next_guard_token: u32,
+    /// Lifetime count of transitions into Open, kept across `reset_breaker`
+    trip_count: u64,
+    /// Lifetime count of requests denied while Open, kept across `reset_breaker`
+    rejected_count: u64,
+    /// Lifetime outcome totals, kept across `reset_breaker`
+    total_successes: u64,
+    total_failures: u64,
+    /// When the breaker entered its current state
+    state_changed_at: Option<u64>,
+    /// Failure categories that count toward tripping; empty means all do
+    trip_categories: BTreeSet<String>,
+    /// Lifetime count of failures in categories that don't trip
+    ignored_failures: u64,
+    /// Slow-start duration after recovering to Closed, 0 disables it
+    ramp_ms: u64,
+    /// When the current slow-start ramp began
+    ramp_started_at: Option<u64>,
+    /// Admission credit in permille; a request is admitted per 1000 earned
+    ramp_credit: u32,
+    /// Whether a Closed-state success forgives one accumulated failure
+    decay_on_success: bool,
+    /// Run the state machine but never deny requests
+    shadow_mode: bool,
+    /// Lifetime count of `allow_request` calls that returned false
+    short_circuited_count: u64,
+    /// Lifetime count of `allow_request` calls, allowed or not
+    evaluations_total: u64,
+    /// Minimum spacing between granted HalfOpen probes (0 = no spacing)
+    probe_interval_ms: u64,
+    last_probe_time: Option<u64>,
+    /// Stop probing once the breaker has been broken this long (0 = never)
+    max_open_duration_ms: u64,
+    /// When the current outage began, i.e. the trip out of Closed
+    broken_since: Option<u64>,
+    /// Open for good until `reset_breaker`, after `max_open_duration_ms`
+    latched: bool,
+    /// Opened by `force_open`: no automatic recovery until `reset_breaker`
+    /// or `release_force_open`
+    forced_open: bool,
+    /// Keep the backoff level across a recovery until the breaker has
+    /// seen this many successes in a row while Closed (0 = no streak rule)
+    healthy_reset_successes: u32,
+    /// ...or has stayed Closed this long (0 = no time rule). With both 0,
+    /// closing clears the backoff level immediately.
+    healthy_reset_ms: u64,
+    /// Consecutive Closed-state successes
+    healthy_streak: u32,
+    /// After `configure_breaker`, failures from before the change don't
+    /// count toward tripping for this long (0 = no grace period)
+    config_grace_ms: u64,
+    config_changed_at: Option<u64>,
+    /// `failure_score` when the config last changed
+    pre_change_score: u64,
+    /// Lifetime failures recorded by `record_timeout`
+    timeout_count: u64,
+    /// Lifetime failures recorded as errors, i.e. all other trip-worthy ones
+    error_count: u64,
+    /// Share of Closed-state requests denied as manual load shedding
+    shed_percent: u32,
+    /// Shedding credit, in percent; a request is shed per 100 earned
+    shed_credit: u32,
+    /// HalfOpen admits this share of traffic instead of a fixed probe
+    /// budget, growing by `half_open_step_percent` per success (0 = off)
+    half_open_start_percent: u32,
+    half_open_step_percent: u32,
+    /// Admission credit for the HalfOpen traffic ramp, in percent
+    probe_credit: u32,
+    /// When false the breaker is bypassed: every request is allowed and
+    /// outcomes are ignored, leaving the state frozen until re-enabled
+    enabled: bool,
+    /// Latencies of the most recent successful `record_result` calls
+    latencies: VecDeque<u64>,
+    /// For this long after recovering, a single failure reopens the
+    /// breaker (0 = off)
+    probation_ms: u64,
+    /// When the breaker last recovered from HalfOpen
+    closed_at: Option<u64>,
+    /// Times of the Closed-state calls in the sliding window, kept for
+    /// `TripMode::Adaptive`
+    call_times: VecDeque<u64>,
+    /// HalfOpen probe slots unanswered for this long are freed (0 = never)
+    probe_timeout_ms: u64,
+    /// When each outstanding HalfOpen probe was granted and how many slots
+    /// it holds, oldest first
+    probe_granted_at: VecDeque<(u64, u32)>,
+    /// Operational context (service, region, owner) reported in status
+    metadata: BTreeMap<String, String>,
+    /// Whether a late success recorded while Open moves to HalfOpen
+    recover_on_open_success: bool,
+    /// Time spent in each state before the current one, for `dwell_times`
+    closed_dwell_ms: u64,
+    open_dwell_ms: u64,
+    half_open_dwell_ms: u64,
+    /// Start of the current state's dwell, unset until a time is observed
+    dwell_since: Option<u64>,
+    /// Set by `seed_rng`: randomized admission draws from `rng_state`
+    /// instead of accruing credit
+    seeded: bool,
+    /// When the most recent trips happened, oldest first
+    trip_times: VecDeque<u64>,
+    trip_history_size: u32,
+    /// Stay Open after the recovery timeout until `confirm_recovery`
+    gated_recovery: bool,
+    /// Decide a quorum window on the results so far once it has been
+    /// HalfOpen this long (0 = wait for every probe)
+    quorum_timeout_ms: u64,
+    /// Set once `flush` drained the event log for shutdown; later
+    /// transitions are still logged
+    quiesced: bool,
+    last_transition_cause: Option<TransitionCause>,
+    /// Failures stamped up to this far behind the last one are taken as
+    /// happening at the last failure time instead of being ignored
+    clock_tolerance_ms: u64,
+    /// Allow one HalfOpen probe at a time, whatever `half_open_max_calls`
+    single_flight: bool,
+    /// Re-open a HalfOpen breaker whose probe budget has been granted in
+    /// full this long without them reporting (0 = wait indefinitely)
+    half_open_stall_ms: u64,
+    /// Anti-flap: `flap_threshold` transitions within `flap_window_ms`
+    /// hold the next Open period for at least `penalty_ms` (0 = off)
+    flap_threshold: u32,
+    flap_window_ms: u64,
+    penalty_ms: u64,
+    /// Times of the most recent transitions, at most `flap_threshold`
+    transition_times: VecDeque<u64>,
+    /// Whether the current Open period is a flapping penalty
+    penalized: bool,
+    /// Most entries kept in `failure_times` and `call_times`
+    max_window_entries: u32,
+    /// Set when the cap dropped entries still inside the window, until
+    /// they would have expired anyway
+    window_truncated: bool,
+}
+
+impl Default for CircuitBreakerState {
+    fn default() -> Self {
+        Self::new(5, 60_000)
+    }
 }
 
 impl CircuitBreakerState {
-    fn new(failure_threshold: u32, recovery_timeout: u64) -> Self {
+    fn new(failure_threshold: u32, recovery_timeout_ms: u64) -> Self {
         Self {
             state: BreakerState::Closed,
             failure_count: 0,
             success_count: 0,
             failure_threshold,
-            recovery_timeout,
+            recovery_timeout_ms,
             last_failure_time: None,
             half_open_calls: 0,
-            half_open_max: 3,
+            half_open_max_calls: 3,
+            half_open_success_threshold: 3,
+            window_ms: 0,
+            failure_times: VecDeque::new(),
+            failure_score: 0,
+            trip_mode: TripMode::Count,
+            close_mode: CloseMode::Consecutive,
+            probe_failures: 0,
+            outcomes: VecDeque::new(),
+            max_recovery_timeout_ms: 0,
+            consecutive_trips: 0,
+            jitter_percent: 0,
+            jitter_ms: 0,
+            rng_state: DEFAULT_RNG_SEED,
+            clock_ms: 0,
+            pending_transitions: Vec::new(),
+            event_log: VecDeque::new(),
+            event_log_size: DEFAULT_EVENT_LOG_SIZE,
+            slow_call_threshold_ms: 0,
+            slow_call_rate_percent: 100,
+            minimum_calls: 0,
+            calls_since_closed: 0,
+            outstanding_guards: VecDeque::new(),
+            next_guard_token: 1,
+            trip_count: 0,
+            rejected_count: 0,
+            total_successes: 0,
+            total_failures: 0,
+            state_changed_at: None,
+            trip_categories: BTreeSet::new(),
+            ignored_failures: 0,
+            ramp_ms: 0,
+            ramp_started_at: None,
+            ramp_credit: 0,
+            decay_on_success: false,
+            shadow_mode: false,
+            short_circuited_count: 0,
+            evaluations_total: 0,
+            probe_interval_ms: 0,
+            last_probe_time: None,
+            max_open_duration_ms: 0,
+            broken_since: None,
+            latched: false,
+            forced_open: false,
+            healthy_reset_successes: 0,
+            healthy_reset_ms: 0,
+            healthy_streak: 0,
+            config_grace_ms: 0,
+            config_changed_at: None,
+            pre_change_score: 0,
+            timeout_count: 0,
+            error_count: 0,
+            shed_percent: 0,
+            shed_credit: 0,
+            half_open_start_percent: 0,
+            half_open_step_percent: 0,
+            probe_credit: 0,
+            enabled: true,
+            latencies: VecDeque::new(),
+            probation_ms: 0,
+            closed_at: None,
+            call_times: VecDeque::new(),
+            probe_timeout_ms: 0,
+            probe_granted_at: VecDeque::new(),
+            metadata: BTreeMap::new(),
+            recover_on_open_success: false,
+            closed_dwell_ms: 0,
+            open_dwell_ms: 0,
+            half_open_dwell_ms: 0,
+            dwell_since: None,
+            seeded: false,
+            trip_times: VecDeque::new(),
+            trip_history_size: DEFAULT_TRIP_HISTORY_SIZE,
+            gated_recovery: false,
+            quorum_timeout_ms: 0,
+            quiesced: false,
+            last_transition_cause: None,
+            clock_tolerance_ms: DEFAULT_CLOCK_TOLERANCE_MS,
+            single_flight: false,
+            half_open_stall_ms: 0,
+            flap_threshold: 0,
+            flap_window_ms: 0,
+            penalty_ms: 0,
+            transition_times: VecDeque::new(),
+            penalized: false,
+            max_window_entries: DEFAULT_MAX_WINDOW_ENTRIES,
+            window_truncated: false,
         }
     }
-}
 
-/// Initialize the circuit breaker with custom thresholds
-#[wasm_bindgen]
-pub fn init_breaker(failure_threshold: u32, recovery_timeout: u64) {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        breaker.failure_threshold = failure_threshold;
-        breaker.recovery_timeout = recovery_timeout;
-        breaker.state = BreakerState::Closed;
-        breaker.failure_count = 0;
-        breaker.success_count = 0;
-    });
-}
+    /// Reset to a fresh Closed breaker with the default configuration
+    /// apart from the given thresholds, so no mode or setting from an
+    /// earlier `init_*` call carries over. Only the lifetime metrics are
+    /// kept, along with the clock, undelivered events and the guard token
+    /// sequence.
+    fn configure(&mut self, failure_threshold: u32, recovery_timeout_ms: u64) {
+        self.reset_runtime();
+        let old = std::mem::take(self);
+        *self = Self {
+            clock_ms: old.clock_ms,
+            pending_transitions: old.pending_transitions,
+            event_log: old.event_log,
+            quiesced: old.quiesced,
+            next_guard_token: old.next_guard_token,
+            state_changed_at: old.state_changed_at,
+            last_transition_cause: old.last_transition_cause,
+            trip_count: old.trip_count,
+            trip_times: old.trip_times,
+            rejected_count: old.rejected_count,
+            total_successes: old.total_successes,
+            total_failures: old.total_failures,
+            ignored_failures: old.ignored_failures,
+            short_circuited_count: old.short_circuited_count,
+            evaluations_total: old.evaluations_total,
+            timeout_count: old.timeout_count,
+            error_count: old.error_count,
+            closed_dwell_ms: old.closed_dwell_ms,
+            open_dwell_ms: old.open_dwell_ms,
+            half_open_dwell_ms: old.half_open_dwell_ms,
+            dwell_since: old.dwell_since,
+            ..Self::new(failure_threshold, recovery_timeout_ms)
+        };
+    }
 
-/// Check if a request should be allowed
-#[wasm_bindgen]
-pub fn allow_request(current_time_ms: u64) -> bool {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        
-        // Check for recovery from Open state
-        if breaker.state == BreakerState::Open {
-            if let Some(last_failure) = breaker.last_failure_time {
-                let elapsed_secs = (current_time_ms - last_failure) / 1000;
-                if elapsed_secs >= breaker.recovery_timeout {
-                    breaker.state = BreakerState::HalfOpen;
-                    breaker.half_open_calls = 0;
-                    breaker.success_count = 0;
+    /// Change thresholds on a live breaker, starting the grace period
+    fn reconfigure(&mut self, failure_threshold: u32, recovery_timeout_ms: u64) {
+        self.set_thresholds(failure_threshold, recovery_timeout_ms);
+        self.config_changed_at = Some(self.clock_ms);
+        self.pre_change_score = self.failure_score;
+    }
+
+    /// Update thresholds, leaving the current state and counters alone
+    fn set_thresholds(&mut self, failure_threshold: u32, recovery_timeout_ms: u64) {
+        self.failure_threshold = failure_threshold;
+        self.recovery_timeout_ms = recovery_timeout_ms;
+    }
+
+    /// Change state, queueing a transition event if it actually changed.
+    /// A transition starts a new guard generation, invalidating old tokens.
+    fn set_state(&mut self, to: BreakerState) {
+        if self.state != to {
+            if let Some(since) = self.dwell_since {
+                let elapsed = self.clock_ms.saturating_sub(since);
+                let dwell = match self.state {
+                    BreakerState::Closed => &mut self.closed_dwell_ms,
+                    BreakerState::Open => &mut self.open_dwell_ms,
+                    BreakerState::HalfOpen => &mut self.half_open_dwell_ms,
+                };
+                *dwell = dwell.saturating_add(elapsed);
+                self.dwell_since = Some(self.clock_ms);
+            }
+            self.outstanding_guards.clear();
+            let transition = Transition {
+                from: self.state,
+                to,
+                at: self.clock_ms,
+            };
+            self.pending_transitions.push(transition);
+            self.event_log.push_back(transition);
+            self.trim_event_log();
+            if self.flap_threshold > 0 {
+                self.transition_times.push_back(self.clock_ms);
+                while self.transition_times.len() > self.flap_threshold as usize {
+                    self.transition_times.pop_front();
                 }
             }
+            self.last_transition_cause = Some(TransitionCause::of(self.state, to));
+            self.state = to;
+            self.state_changed_at = Some(self.clock_ms);
+            self.ramp_started_at = None;
+            if to == BreakerState::Closed {
+                self.broken_since = None;
+            }
         }
-        
-        match breaker.state {
-            BreakerState::Closed => true,
-            BreakerState::Open => false,
+    }
+
+    fn trim_event_log(&mut self) {
+        while self.event_log.len() > self.event_log_size as usize {
+            self.event_log.pop_front();
+        }
+    }
+
+    fn trim_trip_history(&mut self) {
+        while self.trip_times.len() > self.trip_history_size as usize {
+            self.trip_times.pop_front();
+        }
+    }
+
+    /// Empty the event log into a JSON array, oldest first
+    fn drain_events(&mut self) -> String {
+        let events: Vec<Event> = self
+            .event_log
+            .drain(..)
+            .map(|t| Event {
+                from: t.from.as_str(),
+                to: t.to.as_str(),
+                timestamp: t.at,
+            })
+            .collect();
+        serde_json::to_string(&events).expect("events are always serializable")
+    }
+
+    fn observe_time(&mut self, current_time_ms: u64) {
+        self.clock_ms = self.clock_ms.max(current_time_ms);
+        self.dwell_since.get_or_insert(current_time_ms);
+    }
+
+    /// Cumulative time in each state, counting the current one up to
+    /// `current_time_ms`
+    fn dwell_times(&self, current_time_ms: u64) -> DwellTimes {
+        let mut dwell = DwellTimes {
+            closed_ms: self.closed_dwell_ms,
+            open_ms: self.open_dwell_ms,
+            half_open_ms: self.half_open_dwell_ms,
+        };
+        let elapsed = self.dwell_since.map_or(0, |since| current_time_ms.saturating_sub(since));
+        let current = match self.state {
+            BreakerState::Closed => &mut dwell.closed_ms,
+            BreakerState::Open => &mut dwell.open_ms,
+            BreakerState::HalfOpen => &mut dwell.half_open_ms,
+        };
+        *current = current.saturating_add(elapsed);
+        dwell
+    }
+
+    /// Whether an Open breaker should start probing at `current_time_ms`
+    fn recovery_due(&self, current_time_ms: u64) -> bool {
+        !self.gated_recovery && self.recovery_elapsed(current_time_ms)
+    }
+
+    /// Whether an Open breaker has waited out its recovery timeout, which
+    /// under gated recovery only makes it eligible for `confirm_recovery`.
+    /// A forced breaker never has.
+    fn recovery_elapsed(&self, current_time_ms: u64) -> bool {
+        if self.state != BreakerState::Open || self.forced_open || self.latch_due(current_time_ms) {
+            return false;
+        }
+        current_time_ms.saturating_sub(self.opened_at(current_time_ms)) >= self.recovery_wait_ms()
+    }
+
+    /// Start probing once an external health check vouches for recovery.
+    /// Only takes effect on a gated breaker past its recovery timeout.
+    fn confirm_recovery(&mut self, current_time_ms: u64) -> bool {
+        self.observe_time(current_time_ms);
+        if !self.gated_recovery || !self.recovery_elapsed(current_time_ms) {
+            return false;
+        }
+        self.enter_half_open();
+        self.last_transition_cause = Some(TransitionCause::RecoveryConfirmed);
+        true
+    }
+
+    /// Open and not yet due to probe at `current_time_ms`
+    fn is_open_at(&self, current_time_ms: u64) -> bool {
+        self.state == BreakerState::Open && !self.recovery_due(current_time_ms)
+    }
+
+    /// Whether the breaker is, or at `current_time_ms` should become,
+    /// latched Open: it has been Open or probing without ever closing for
+    /// `max_open_duration_ms`. Only checked while Open so an in-flight
+    /// probe can still close it.
+    fn latch_due(&self, current_time_ms: u64) -> bool {
+        self.latched
+            || (self.state == BreakerState::Open
+                && self.max_open_duration_ms > 0
+                && self.broken_since.is_some_and(|since| {
+                    current_time_ms.saturating_sub(since) >= self.max_open_duration_ms
+                }))
+    }
+
+    /// Reference point for the recovery timer. An Open breaker without a
+    /// recorded failure (e.g. restored from inconsistent state) counts from
+    /// `current_time_ms` rather than staying Open forever.
+    fn opened_at(&self, current_time_ms: u64) -> u64 {
+        self.last_failure_time.unwrap_or(current_time_ms)
+    }
+
+    /// How long the current Open period lasts, including backoff and jitter
+    fn recovery_wait_ms(&self) -> u64 {
+        let wait = self
+            .effective_recovery_timeout_ms()
+            .saturating_add(self.jitter_ms);
+        if self.penalized {
+            wait.max(self.penalty_ms)
+        } else {
+            wait
+        }
+    }
+
+    /// Whether the last `flap_threshold` transitions all happened within
+    /// `flap_window_ms`
+    fn flapping(&self) -> bool {
+        self.flap_threshold > 0
+            && self.transition_times.len() >= self.flap_threshold as usize
+            && self.transition_times.front().is_some_and(|&oldest| {
+                self.clock_ms.saturating_sub(oldest) <= self.flap_window_ms
+            })
+    }
+
+    /// Milliseconds until an Open breaker may probe, or `None` unless Open
+    /// and able to recover
+    fn time_until_retry(&self, current_time_ms: u64) -> Option<u64> {
+        if self.state != BreakerState::Open || self.forced_open || self.latch_due(current_time_ms) {
+            return None;
+        }
+        let retry_at = self
+            .opened_at(current_time_ms)
+            .saturating_add(self.recovery_wait_ms());
+        Some(retry_at.saturating_sub(current_time_ms))
+    }
+
+    /// Earliest time a request could be admitted: `current_time_ms` if one
+    /// would be now, the end of the Open period (with backoff and jitter),
+    /// or the end of the probe spacing. `None` when no time can be
+    /// predicted: latched or gated Open, or a HalfOpen budget waiting on
+    /// results.
+    fn next_probe_time(&self, current_time_ms: u64) -> Option<u64> {
+        if !self.enabled {
+            return Some(current_time_ms);
+        }
+        match self.state {
+            BreakerState::Closed => Some(current_time_ms),
+            BreakerState::Open if self.gated_recovery => None,
+            BreakerState::Open => {
+                let wait = self.time_until_retry(current_time_ms)?;
+                Some(current_time_ms.saturating_add(wait))
+            }
             BreakerState::HalfOpen => {
-                if breaker.half_open_calls < breaker.half_open_max {
-                    breaker.half_open_calls += 1;
-                    true
-                } else {
-                    false
+                let ramped = self.half_open_start_percent > 0;
+                if (!ramped && self.half_open_calls >= self.half_open_max_calls)
+                    || self.probe_in_flight()
+                {
+                    return None;
                 }
+                let spaced_until = self
+                    .last_probe_time
+                    .map_or(0, |last| last.saturating_add(self.probe_interval_ms));
+                Some(current_time_ms.max(spaced_until))
             }
         }
-    })
-}
+    }
 
-/// Record a successful operation
-#[wasm_bindgen]
-pub fn record_success() {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        breaker.success_count += 1;
-        
-        if breaker.state == BreakerState::HalfOpen {
-            if breaker.success_count >= breaker.half_open_max {
-                breaker.state = BreakerState::Closed;
-                breaker.failure_count = 0;
-                breaker.success_count = 0;
+    /// Report what `allow_request` would decide without consuming a probe
+    /// slot, credit or random draw, or committing a recovery transition
+    fn would_allow(&self, current_time_ms: u64) -> bool {
+        if !self.enabled || self.shadow_mode {
+            return true;
+        }
+        if self.recovery_due(current_time_ms) {
+            // A fresh HalfOpen window starts with its full probe budget
+            return self.half_open_max_calls > 0;
+        }
+        // The seeded draws `allow_request` would make, in the same order
+        let mut rng = self.rng_state;
+        let mut draw = || {
+            self.seeded.then(|| {
+                rng = xorshift(rng);
+                unit_sample(rng)
+            })
+        };
+        match self.state {
+            BreakerState::Closed => {
+                let ramped = match self.ramp_permille(current_time_ms) {
+                    Some(permille) => match draw() {
+                        Some(sample) => sample * 1000.0 < permille as f64,
+                        None => self.ramp_credit + permille >= 1000,
+                    },
+                    None => true,
+                };
+                let shed = self.shed_percent > 0
+                    && match draw() {
+                        Some(sample) => sample * 100.0 < self.shed_percent as f64,
+                        None => self.shed_credit + self.shed_percent >= 100,
+                    };
+                ramped && !shed
+            }
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                let admitted = match self.half_open_traffic_percent() {
+                    Some(percent) => match draw() {
+                        Some(sample) => sample * 100.0 < percent as f64,
+                        None => self.probe_credit + percent >= 100,
+                    },
+                    None => self.half_open_calls < self.half_open_max_calls,
+                };
+                admitted && !self.probe_in_flight() && self.probe_spacing_elapsed(current_time_ms)
             }
         }
-    });
-}
+    }
 
-/// Record a failed operation
-#[wasm_bindgen]
-pub fn record_failure(current_time_ms: u64) {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        breaker.failure_count += 1;
-        breaker.last_failure_time = Some(current_time_ms);
-        
-        if breaker.state == BreakerState::HalfOpen {
-            breaker.state = BreakerState::Open;
-        } else if breaker.failure_count >= breaker.failure_threshold {
-            breaker.state = BreakerState::Open;
+    /// Share of HalfOpen traffic admitted under the traffic ramp, or `None`
+    /// when HalfOpen uses a fixed probe budget
+    fn half_open_traffic_percent(&self) -> Option<u32> {
+        if self.half_open_start_percent == 0 {
+            return None;
         }
-    });
-}
+        let step = self.half_open_step_percent.saturating_mul(self.success_count);
+        Some(self.half_open_start_percent.saturating_add(step).min(100))
+    }
 
-/// Get current breaker state as JSON string
-#[wasm_bindgen]
-pub fn get_status() -> String {
-    BREAKER.with(|b| {
-        let breaker = b.borrow();
-        format!(
-            r#"{{"state":"{}","failures":{},"successes":{}}}"#,
-            breaker.state.as_str(),
-            breaker.failure_count,
-            breaker.success_count
-        )
-    })
-}
+    /// Whether to shed a Closed-state request. A caller-supplied `sample`
+    /// in [0, 1), or a seeded draw, sheds when below `shed_percent`;
+    /// without one, credit accrues per request so exactly that share is shed.
+    fn shed(&mut self, sample: Option<f64>) -> bool {
+        if self.shed_percent == 0 {
+            return false;
+        }
+        if let Some(sample) = sample.or_else(|| self.seeded_sample()) {
+            return sample * 100.0 < self.shed_percent as f64;
+        }
+        self.shed_credit += self.shed_percent;
+        if self.shed_credit >= 100 {
+            self.shed_credit -= 100;
+            true
+        } else {
+            false
+        }
+    }
 
-/// Force the breaker open (kill switch)
-#[wasm_bindgen]
-pub fn force_open(current_time_ms: u64) {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        breaker.state = BreakerState::Open;
-        breaker.last_failure_time = Some(current_time_ms);
-    });
-}
+    /// Apply the HalfOpen traffic ramp. A caller-supplied `sample` in
+    /// [0, 1), or a seeded draw, admits when below the current share;
+    /// without one, credit accrues per request so exactly every Nth
+    /// request passes.
+    fn probe_rate_admit(&mut self, sample: Option<f64>) -> bool {
+        let Some(percent) = self.half_open_traffic_percent() else {
+            return true;
+        };
+        if let Some(sample) = sample.or_else(|| self.seeded_sample()) {
+            return sample * 100.0 < percent as f64;
+        }
+        self.probe_credit += percent;
+        if self.probe_credit >= 100 {
+            self.probe_credit -= 100;
+            true
+        } else {
+            false
+        }
+    }
 
-/// Reset the breaker to closed state
-#[wasm_bindgen]
-pub fn reset_breaker() {
-    BREAKER.with(|b| {
-        let mut breaker = b.borrow_mut();
-        breaker.state = BreakerState::Closed;
-        breaker.failure_count = 0;
-        breaker.success_count = 0;
-        breaker.half_open_calls = 0;
-        breaker.last_failure_time = None;
-    });
-}
+    /// Whether `probe_interval_ms` has passed since the last granted probe
+    fn probe_spacing_elapsed(&self, current_time_ms: u64) -> bool {
+        self.last_probe_time.is_none_or(|last| {
+            current_time_ms.saturating_sub(last) >= self.probe_interval_ms
+        })
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Whether the probe budget has room, without consuming a slot. Uses
+    /// the committed state: an Open breaker reports false even once its
+    /// recovery timeout has elapsed, until a request moves it to HalfOpen.
+    fn probe_available(&self) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                self.half_open_calls < self.half_open_max_calls && !self.probe_in_flight()
+            }
+        }
+    }
 
-    #[test]
-    fn test_breaker_starts_closed() {
-        reset_breaker();
-        assert!(allow_request(0));
+    /// Probes the budget still has room for: the full budget while Closed,
+    /// since a fresh HalfOpen window would start with it, and 0 while Open
+    fn probe_slots_remaining(&self) -> u32 {
+        match self.state {
+            BreakerState::Closed => self.half_open_max_calls,
+            BreakerState::Open => 0,
+            BreakerState::HalfOpen if self.probe_in_flight() => 0,
+            BreakerState::HalfOpen => self.half_open_max_calls.saturating_sub(self.half_open_calls),
+        }
     }
 
-    #[test]
-    fn test_breaker_opens_after_failures() {
-        init_breaker(3, 60);
-        reset_breaker();
-        
-        record_failure(1000);
-        record_failure(2000);
-        assert!(allow_request(3000)); // Still closed after 2 failures
-        
-        record_failure(3000);
-        assert!(!allow_request(4000)); // Now open after 3 failures
+    /// Whether single-flight mode is holding back probes until the
+    /// outstanding one reports, or is reclaimed by `probe_timeout_ms`
+    fn probe_in_flight(&self) -> bool {
+        self.single_flight && !self.probe_granted_at.is_empty()
+    }
+
+    /// Share of traffic admitted during slow start, in permille, rising
+    /// linearly from 100 (10%) at close to 1000 (100%) after `ramp_ms`.
+    /// `None` outside a ramp.
+    fn ramp_permille(&self, current_time_ms: u64) -> Option<u32> {
+        let started = self.ramp_started_at?;
+        let elapsed = current_time_ms.saturating_sub(started);
+        if elapsed >= self.ramp_ms {
+            return None;
+        }
+        Some(100 + (900 * elapsed / self.ramp_ms) as u32)
+    }
+
+    /// Admit a Closed-state request during slow start. Each request earns
+    /// credit at the current admission rate and one is let through for
+    /// every 1000 permille earned, so exactly every Nth request passes.
+    fn ramp_admit(&mut self, current_time_ms: u64) -> bool {
+        let Some(permille) = self.ramp_permille(current_time_ms) else {
+            self.ramp_started_at = None;
+            return true;
+        };
+        if let Some(sample) = self.seeded_sample() {
+            return sample * 1000.0 < permille as f64;
+        }
+        self.ramp_credit += permille;
+        if self.ramp_credit >= 1000 {
+            self.ramp_credit -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn allow_request(&mut self, current_time_ms: u64) -> bool {
+        self.evaluate_request(current_time_ms).allowed
+    }
+
+    /// In shadow mode the decision is still made and accounted for, but
+    /// the request is always let through; `reason` keeps the real decision
+    fn evaluate_request(&mut self, current_time_ms: u64) -> Evaluation {
+        self.evaluate_request_sampled(current_time_ms, None, 1)
+    }
+
+    /// `cost` is how many HalfOpen probe slots the request needs
+    fn evaluate_request_sampled(
+        &mut self,
+        current_time_ms: u64,
+        sample: Option<f64>,
+        cost: u32,
+    ) -> Evaluation {
+        self.evaluations_total = self.evaluations_total.saturating_add(1);
+        let decision = if self.enabled {
+            self.decide_request(current_time_ms, sample, cost)
+        } else {
+            Decision::Disabled
+        };
+        let allowed = decision.allowed() || self.shadow_mode;
+        if !allowed {
+            self.short_circuited_count = self.short_circuited_count.saturating_add(1);
+        }
+        Evaluation {
+            allowed,
+            state: self.state.as_str(),
+            reason: decision.as_str(),
+        }
+    }
+
+    fn decide_request(&mut self, current_time_ms: u64, sample: Option<f64>, cost: u32) -> Decision {
+        self.observe_time(current_time_ms);
+        self.compact(current_time_ms);
+        if self.state == BreakerState::Open && self.last_failure_time.is_none() {
+            self.last_failure_time = Some(current_time_ms);
+        }
+        if self.latch_due(current_time_ms) {
+            self.latched = true;
+        }
+        // Check for recovery from Open state. The transition and the probe
+        // grant below happen under the same registry borrow, so interleaved
+        // callers can't each see a fresh budget around the transition.
+        if self.recovery_due(current_time_ms) {
+            self.enter_half_open();
+        }
+        if self.quorum_overdue(current_time_ms) {
+            self.finalize_quorum();
+        }
+        if self.probes_stalled(current_time_ms) {
+            self.last_failure_time = Some(self.clock_ms);
+            self.fail_probe_window();
+        }
+
+        match self.state {
+            BreakerState::Closed => {
+                if !self.ramp_admit(current_time_ms) {
+                    Decision::SlowStartThrottled
+                } else if self.shed(sample) {
+                    Decision::Shed
+                } else {
+                    Decision::Closed
+                }
+            }
+            BreakerState::Open => {
+                self.rejected_count = self.rejected_count.saturating_add(1);
+                if self.latched {
+                    Decision::OpenLatched
+                } else if self.forced_open {
+                    Decision::OpenForced
+                } else if self.recovery_elapsed(current_time_ms) {
+                    Decision::OpenAwaitingConfirmation
+                } else {
+                    Decision::OpenRejected
+                }
+            }
+            BreakerState::HalfOpen => {
+                self.reclaim_stuck_probes(current_time_ms);
+                let ramped = self.half_open_start_percent > 0;
+                if !ramped && self.half_open_calls.saturating_add(cost) > self.half_open_max_calls {
+                    Decision::HalfOpenBudgetExhausted
+                } else if self.probe_in_flight() {
+                    Decision::HalfOpenProbeInFlight
+                } else if !self.probe_spacing_elapsed(current_time_ms) {
+                    Decision::HalfOpenProbeSpacing
+                } else if !self.probe_rate_admit(sample) {
+                    Decision::HalfOpenThrottled
+                } else {
+                    self.half_open_calls = self.half_open_calls.saturating_add(cost);
+                    self.probe_granted_at.push_back((current_time_ms, cost));
+                    self.last_probe_time = Some(current_time_ms);
+                    Decision::HalfOpenProbe
+                }
+            }
+        }
+    }
+
+    /// Timeout the next Open->HalfOpen transition will use: the current
+    /// Open period's wait including jitter, the base timeout while Closed,
+    /// or the backed-off timeout a failed probe would bring while HalfOpen
+    /// (jitter is drawn when the breaker trips, so it isn't included)
+    fn current_recovery_timeout_ms(&self) -> u64 {
+        match self.state {
+            BreakerState::Closed => self.recovery_timeout_ms,
+            BreakerState::Open => self.recovery_wait_ms(),
+            BreakerState::HalfOpen => {
+                self.backoff_timeout_ms(self.consecutive_trips.saturating_add(1))
+            }
+        }
+    }
+
+    /// Set a metadata entry; new keys are rejected once the map is full
+    fn set_metadata(&mut self, key: &str, value: &str) -> Result<(), BreakerError> {
+        if self.metadata.len() >= MAX_METADATA_KEYS && !self.metadata.contains_key(key) {
+            return Err(BreakerError::InvalidConfig(format!(
+                "at most {} metadata keys",
+                MAX_METADATA_KEYS
+            )));
+        }
+        self.metadata.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Free the slots of probes granted at least `probe_timeout_ms` ago
+    /// that never reported back, so an abandoned probe can't block recovery
+    fn reclaim_stuck_probes(&mut self, current_time_ms: u64) {
+        if self.probe_timeout_ms == 0 {
+            return;
+        }
+        while self
+            .probe_granted_at
+            .front()
+            .is_some_and(|&(at, _)| current_time_ms.saturating_sub(at) >= self.probe_timeout_ms)
+        {
+            self.release_probe();
+        }
+    }
+
+    /// Retire the oldest outstanding probe, freeing the slots it holds.
+    /// Results aren't matched to grants, so the oldest stands in for the
+    /// probe that reported; one without a grant record holds one slot.
+    fn release_probe(&mut self) {
+        let cost = self.probe_granted_at.pop_front().map_or(1, |(_, cost)| cost);
+        self.half_open_calls = self.half_open_calls.saturating_sub(cost);
+    }
+
+    /// Start a fresh HalfOpen probe window. The Closed-state failure count
+    /// starts over here: HalfOpen reopens on a failed probe whatever the
+    /// threshold, and a recovered breaker needs the full threshold again.
+    fn enter_half_open(&mut self) {
+        self.set_state(BreakerState::HalfOpen);
+        self.forced_open = false;
+        self.penalized = false;
+        self.failure_count = 0;
+        self.failure_score = 0;
+        self.failure_times.clear();
+        self.half_open_calls = 0;
+        self.probe_granted_at.clear();
+        self.success_count = 0;
+        self.probe_failures = 0;
+        self.last_probe_time = None;
+        // Just enough credit for the first request of the window to probe
+        self.probe_credit = 100u32.saturating_sub(self.half_open_start_percent);
+    }
+
+    /// Recovery timeout, doubled for every consecutive failed recovery and
+    /// capped at `max_recovery_timeout_ms` when backoff is enabled
+    fn effective_recovery_timeout_ms(&self) -> u64 {
+        self.backoff_timeout_ms(self.consecutive_trips)
+    }
+
+    fn backoff_timeout_ms(&self, consecutive_trips: u32) -> u64 {
+        if self.max_recovery_timeout_ms == 0 {
+            return self.recovery_timeout_ms;
+        }
+        let factor = 1u64 << consecutive_trips.min(63);
+        self.recovery_timeout_ms
+            .saturating_mul(factor)
+            .min(self.max_recovery_timeout_ms)
+    }
+
+    /// In HalfOpen, each success frees its probe slot and the breaker
+    /// closes after `half_open_success_threshold` consecutive ones; any
+    /// failure in between restarts the run.
+    fn record_success(&mut self) {
+        self.record_success_outcome(Outcome::Success);
+    }
+
+    /// Record a call with its latency. A failed call counts only as a
+    /// failure, even if it was also slow, so it can't trip the breaker twice.
+    fn record_result(&mut self, success: bool, latency_ms: u64, current_time_ms: u64) {
+        if !success {
+            self.record_failure(current_time_ms);
+            return;
+        }
+        self.observe_time(current_time_ms);
+        if self.enabled {
+            self.latencies.push_back(latency_ms);
+            if self.latencies.len() > LATENCY_SAMPLES {
+                self.latencies.pop_front();
+            }
+        }
+        let slow = self.slow_call_threshold_ms > 0 && latency_ms >= self.slow_call_threshold_ms;
+        self.record_success_outcome(if slow { Outcome::SlowSuccess } else { Outcome::Success });
+    }
+
+    fn record_success_outcome(&mut self, outcome: Outcome) {
+        if !self.enabled {
+            return;
+        }
+        self.total_successes = self.total_successes.saturating_add(1);
+        if self.state == BreakerState::Closed {
+            self.calls_since_closed = self.calls_since_closed.saturating_add(1);
+            self.healthy_streak = self.healthy_streak.saturating_add(1);
+            self.clear_backoff_if_healthy();
+            if self.decay_on_success {
+                self.decay_failure();
+            }
+            self.push_outcome(outcome);
+            if self.should_trip() {
+                // Slow calls tripped it; time recovery from this call
+                self.last_failure_time = Some(self.clock_ms);
+                self.trip();
+                return;
+            }
+        }
+
+        if self.state == BreakerState::Open {
+            // Only a request admitted before the trip can succeed now
+            if self.recover_on_open_success && !self.latched && !self.forced_open {
+                self.enter_half_open();
+                self.last_transition_cause = Some(TransitionCause::LateSuccess);
+            }
+            return;
+        }
+        if self.state != BreakerState::HalfOpen {
+            return;
+        }
+        self.success_count = self.success_count.saturating_add(1);
+        match self.close_mode {
+            CloseMode::Consecutive => {
+                if self.success_count >= self.half_open_success_threshold {
+                    self.close();
+                } else {
+                    self.release_probe();
+                }
+            }
+            CloseMode::Quorum => {
+                // Quorum slots stay taken for the window, only the grant retires
+                self.probe_granted_at.pop_front();
+                self.settle_quorum();
+            }
+        }
+    }
+
+    /// Recover from HalfOpen to a fresh Closed state
+    fn close(&mut self) {
+        self.set_state(BreakerState::Closed);
+        if self.ramp_ms > 0 {
+            self.ramp_started_at = Some(self.clock_ms);
+            self.ramp_credit = 0;
+        }
+        self.failure_count = 0;
+        self.failure_score = 0;
+        self.success_count = 0;
+        self.outcomes.clear();
+        self.call_times.clear();
+        if self.healthy_reset_successes == 0 && self.healthy_reset_ms == 0 {
+            self.consecutive_trips = 0;
+        }
+        self.healthy_streak = 0;
+        self.calls_since_closed = 0;
+        // The counts the grace period discounts are gone
+        self.config_changed_at = None;
+        self.closed_at = Some(self.clock_ms);
+    }
+
+    /// Whether the breaker recovered less than `probation_ms` ago
+    fn in_probation(&self) -> bool {
+        self.probation_ms > 0
+            && self.closed_at.is_some_and(|at| self.clock_ms.saturating_sub(at) < self.probation_ms)
+    }
+
+    /// Once a Closed breaker has been healthy long enough, forget the
+    /// backoff level and the stale failure time from the last outage, so
+    /// an unrelated future trip starts from the base timeout
+    fn clear_backoff_if_healthy(&mut self) {
+        let streak_done = self.healthy_reset_successes > 0
+            && self.healthy_streak >= self.healthy_reset_successes;
+        let time_done = self.healthy_reset_ms > 0
+            && self
+                .state_changed_at
+                .is_some_and(|at| self.clock_ms.saturating_sub(at) >= self.healthy_reset_ms);
+        if streak_done || time_done {
+            self.consecutive_trips = 0;
+            self.last_failure_time = None;
+        }
+    }
+
+    /// Re-open after a failed HalfOpen window, backing off further
+    fn fail_probe_window(&mut self) {
+        self.success_count = 0;
+        self.consecutive_trips = self.consecutive_trips.saturating_add(1);
+        self.trip();
+    }
+
+    /// Decide a quorum window once every probe has reported back
+    fn settle_quorum(&mut self) {
+        let results = self.success_count.saturating_add(self.probe_failures);
+        if results < self.half_open_max_calls {
+            return;
+        }
+        if self.success_count >= self.half_open_success_threshold {
+            self.close();
+        } else {
+            self.fail_probe_window();
+        }
+    }
+
+    /// Whether a quorum window with outstanding probes has run past
+    /// `quorum_timeout_ms`
+    fn quorum_overdue(&self, current_time_ms: u64) -> bool {
+        self.close_mode == CloseMode::Quorum
+            && self.state == BreakerState::HalfOpen
+            && self.quorum_timeout_ms > 0
+            && self.half_open_calls > 0
+            && self.state_changed_at.is_some_and(|at| {
+                current_time_ms.saturating_sub(at) >= self.quorum_timeout_ms
+            })
+    }
+
+    /// Whether every HalfOpen probe slot has been granted, some are still
+    /// outstanding, and the last was granted `half_open_stall_ms` ago
+    fn probes_stalled(&self, current_time_ms: u64) -> bool {
+        self.state == BreakerState::HalfOpen
+            && self.half_open_stall_ms > 0
+            && self.half_open_calls >= self.half_open_max_calls
+            && !self.probe_granted_at.is_empty()
+            && self.last_probe_time.is_some_and(|at| {
+                current_time_ms.saturating_sub(at) >= self.half_open_stall_ms
+            })
+    }
+
+    /// Decide an overdue quorum window on the probes that did report: close
+    /// if they succeeded in at least the ratio the full window requires,
+    /// otherwise re-open, timing the Open period from now
+    fn finalize_quorum(&mut self) {
+        let reported = self.success_count.saturating_add(self.probe_failures) as u64;
+        let ratio_met = reported > 0
+            && self.success_count as u64 * self.half_open_max_calls as u64
+                >= self.half_open_success_threshold as u64 * reported;
+        if ratio_met {
+            self.close();
+        } else {
+            self.last_failure_time = Some(self.clock_ms);
+            self.fail_probe_window();
+        }
+    }
+
+    fn record_failure(&mut self, current_time_ms: u64) {
+        self.record_failure_weighted(1000, current_time_ms);
+    }
+
+    fn record_failure_weighted(&mut self, weight_milli: u64, current_time_ms: u64) {
+        self.record_failure_kind(FailureKind::Error, weight_milli, current_time_ms);
+    }
+
+    /// Timestamp a failure at `current_time_ms` is recorded with. One
+    /// slightly behind the last failure, within `clock_tolerance_ms`, is
+    /// clock noise and is moved up to it; anything further behind is
+    /// stale and yields `None`.
+    fn failure_time(&self, current_time_ms: u64) -> Option<u64> {
+        match self.last_failure_time {
+            Some(last) if current_time_ms < last => {
+                (last - current_time_ms <= self.clock_tolerance_ms).then_some(last)
+            }
+            _ => Some(current_time_ms),
+        }
+    }
+
+    /// A failure stamped too far before the last recorded one is ignored,
+    /// so a stale or skewed clock can't rewind the recovery timer.
+    fn record_failure_kind(&mut self, kind: FailureKind, weight_milli: u64, current_time_ms: u64) {
+        if !self.enabled {
+            return;
+        }
+        let Some(current_time_ms) = self.failure_time(current_time_ms) else {
+            return;
+        };
+        self.observe_time(current_time_ms);
+        if self.state == BreakerState::Closed {
+            self.clear_backoff_if_healthy();
+            self.healthy_streak = 0;
+        }
+        self.total_failures = self.total_failures.saturating_add(1);
+        match kind {
+            FailureKind::Error => self.error_count = self.error_count.saturating_add(1),
+            FailureKind::Timeout => self.timeout_count = self.timeout_count.saturating_add(1),
+        }
+        if self.window_ms > 0 {
+            self.record_windowed_failure(weight_milli, current_time_ms);
+        } else {
+            self.failure_count = self.failure_count.saturating_add(1);
+            self.failure_score = self.failure_score.saturating_add(weight_milli);
+        }
+        self.last_failure_time = Some(current_time_ms);
+        if self.state == BreakerState::Closed {
+            self.calls_since_closed = self.calls_since_closed.saturating_add(1);
+            self.push_outcome(Outcome::Failure);
+        }
+
+        if self.state == BreakerState::HalfOpen {
+            self.probe_granted_at.pop_front();
+            match self.close_mode {
+                CloseMode::Consecutive => self.fail_probe_window(),
+                CloseMode::Quorum => {
+                    self.probe_failures = self.probe_failures.saturating_add(1);
+                    self.settle_quorum();
+                }
+            }
+        } else if self.state == BreakerState::Closed && (self.in_probation() || self.should_trip())
+        {
+            self.trip();
+        }
+    }
+
+    /// Record a failure that proves the dependency is broken, opening the
+    /// breaker whatever the failure count. Unlike `force_open` it trips
+    /// normally, so the recovery timeout and backoff still apply.
+    fn record_fatal_failure(&mut self, current_time_ms: u64) {
+        if !self.enabled || self.failure_time(current_time_ms).is_none() {
+            return;
+        }
+        self.record_failure(current_time_ms);
+        match self.state {
+            BreakerState::Closed => self.trip(),
+            BreakerState::HalfOpen => self.fail_probe_window(),
+            BreakerState::Open => {}
+        }
+    }
+
+    /// Record a failure tagged with an error category. Categories that are
+    /// not trip-worthy are counted but otherwise leave the state untouched,
+    /// apart from freeing a HalfOpen probe slot; the run of HalfOpen
+    /// successes carries on. A quorum window keeps its slots for the whole
+    /// window, so there only the probe's grant is retired.
+    fn record_failure_categorized(&mut self, category: &str, current_time_ms: u64) {
+        if !self.enabled {
+            return;
+        }
+        if self.trip_categories.is_empty() || self.trip_categories.contains(category) {
+            self.record_failure(current_time_ms);
+            return;
+        }
+        self.observe_time(current_time_ms);
+        self.total_failures = self.total_failures.saturating_add(1);
+        self.ignored_failures = self.ignored_failures.saturating_add(1);
+        if self.state == BreakerState::HalfOpen {
+            match self.close_mode {
+                CloseMode::Consecutive => self.release_probe(),
+                CloseMode::Quorum => {
+                    self.probe_granted_at.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Move to Open, drawing this period's recovery jitter
+    fn trip(&mut self) {
+        if self.state != BreakerState::Open {
+            self.trip_count = self.trip_count.saturating_add(1);
+            self.trip_times.push_back(self.clock_ms);
+            self.trim_trip_history();
+        }
+        if self.state == BreakerState::Closed {
+            self.broken_since = Some(self.clock_ms);
+        }
+        if self.state == BreakerState::HalfOpen {
+            // Don't let a failed probe window's progress leak into the next
+            self.half_open_calls = 0;
+            self.probe_failures = 0;
+            self.probe_granted_at.clear();
+        }
+        self.success_count = 0;
+        self.set_state(BreakerState::Open);
+        if self.flapping() {
+            // Serve the penalty, then start counting transitions afresh
+            self.penalized = true;
+            self.transition_times.clear();
+        }
+        self.last_failure_time.get_or_insert(self.clock_ms);
+        self.outcomes.clear();
+        self.call_times.clear();
+        self.jitter_ms = 0;
+        if self.jitter_percent > 0 {
+            let timeout_ms = self.effective_recovery_timeout_ms();
+            let max_jitter = timeout_ms.saturating_mul(self.jitter_percent as u64) / 100;
+            self.jitter_ms = self.next_random() % (max_jitter + 1);
+        }
+    }
+
+    fn seed_rng(&mut self, seed: u64) {
+        self.rng_state = if seed == 0 { DEFAULT_RNG_SEED } else { seed };
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = xorshift(self.rng_state);
+        self.rng_state
+    }
+
+    /// A draw in [0, 1) once `seed_rng` was called, otherwise None
+    fn seeded_sample(&mut self) -> Option<f64> {
+        if !self.seeded {
+            return None;
+        }
+        Some(unit_sample(self.next_random()))
+    }
+
+    /// `failure_score`, minus failures from before a config change while
+    /// its grace period lasts
+    fn effective_failure_score(&self) -> u64 {
+        let Some(changed_at) = self.config_changed_at else {
+            return self.failure_score;
+        };
+        if self.clock_ms.saturating_sub(changed_at) >= self.config_grace_ms {
+            return self.failure_score;
+        }
+        if self.window_ms > 0 {
+            self.failure_times
+                .iter()
+                .filter(|&&(t, _)| t >= changed_at)
+                .map(|&(_, w)| w)
+                .sum()
+        } else {
+            self.failure_score.saturating_sub(self.pre_change_score)
+        }
+    }
+
+    /// Whether the Closed-state failure history warrants opening
+    fn should_trip(&self) -> bool {
+        let failures_tripped = match self.trip_mode {
+            TripMode::Count => {
+                self.calls_since_closed >= self.minimum_calls
+                    && self.effective_failure_score() >= self.failure_threshold as u64 * 1000
+            }
+            TripMode::Rate { failure_rate_percent, minimum_calls } => {
+                self.outcome_rate_reached(Outcome::Failure, failure_rate_percent, minimum_calls)
+            }
+            TripMode::CountWindow { .. } => {
+                self.calls_since_closed >= self.minimum_calls
+                    && self.failure_count >= self.failure_threshold
+            }
+            TripMode::Adaptive { k_milli } => {
+                let cutoff = self.clock_ms.saturating_sub(self.window_ms);
+                let failures: u64 = self
+                    .failure_times
+                    .iter()
+                    .filter(|&&(t, _)| t >= cutoff)
+                    .map(|&(_, w)| w)
+                    .sum();
+                let calls = self.call_times.len() as f64;
+                self.calls_since_closed >= self.minimum_calls
+                    && failures as f64 > k_milli as f64 * calls.sqrt()
+            }
+        };
+        let slow_tripped = self.slow_call_threshold_ms > 0
+            && self.outcome_rate_reached(
+                Outcome::SlowSuccess,
+                self.slow_call_rate_percent,
+                self.outcome_window(),
+            );
+        failures_tripped || slow_tripped
+    }
+
+    /// Whether `outcome` makes up at least `percent` of the window, once
+    /// the window holds `minimum_calls` entries
+    fn outcome_rate_reached(&self, outcome: Outcome, percent: u32, minimum_calls: u32) -> bool {
+        let total = self.outcomes.len() as u64;
+        if total == 0 || total < minimum_calls as u64 {
+            return false;
+        }
+        let matching = self.outcomes.iter().filter(|&&o| o == outcome).count() as u64;
+        matching * 100 >= percent as u64 * total
+    }
+
+    /// Capacity of the outcome window
+    fn outcome_window(&self) -> u32 {
+        match self.trip_mode {
+            TripMode::Rate { minimum_calls, .. } => minimum_calls,
+            TripMode::CountWindow { window_size } => window_size,
+            TripMode::Count | TripMode::Adaptive { .. } => DEFAULT_OUTCOME_WINDOW,
+        }
+    }
+
+    /// Add a Closed-state outcome, keeping `success_count` in step with
+    /// the successes left in the window, and in count-window mode the
+    /// failure count with the failures left in it
+    fn push_outcome(&mut self, outcome: Outcome) {
+        if let TripMode::Adaptive { .. } = self.trip_mode {
+            let cutoff = self.clock_ms.saturating_sub(self.window_ms);
+            while self.call_times.front().is_some_and(|&t| t < cutoff) {
+                self.call_times.pop_front();
+            }
+            self.call_times.push_back(self.clock_ms);
+            self.cap_windows();
+        }
+        self.outcomes.push_back(outcome);
+        if outcome != Outcome::Failure {
+            self.success_count = self.success_count.saturating_add(1);
+        }
+        while self.outcomes.len() > self.outcome_window() as usize {
+            if self.outcomes.pop_front() != Some(Outcome::Failure) {
+                self.success_count = self.success_count.saturating_sub(1);
+            }
+        }
+        if let TripMode::CountWindow { .. } = self.trip_mode {
+            let failures = self.outcomes.iter().filter(|&&o| o == Outcome::Failure).count();
+            self.failure_count = failures as u32;
+            self.failure_score = failures as u64 * 1000;
+        }
+    }
+
+    /// Continuous health in [0.0, 1.0]: the success ratio of the outcome
+    /// window while Closed (1.0 with no data), progress toward the close
+    /// threshold while HalfOpen, and 0.0 while Open
+    fn health_score(&self) -> f64 {
+        let score = match self.state {
+            BreakerState::Open => 0.0,
+            BreakerState::HalfOpen => {
+                self.success_count as f64 / self.half_open_success_threshold.max(1) as f64
+            }
+            BreakerState::Closed => {
+                if self.outcomes.is_empty() {
+                    return 1.0;
+                }
+                let failures = self.outcomes.iter().filter(|&&o| o == Outcome::Failure).count();
+                1.0 - failures as f64 / self.outcomes.len() as f64
+            }
+        };
+        score.clamp(0.0, 1.0)
+    }
+
+    /// Mean of the recent successful-call latencies, 0.0 with no data
+    fn latency_mean(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        self.latencies.iter().sum::<u64>() as f64 / self.latencies.len() as f64
+    }
+
+    /// Nearest-rank percentile of the recent successful-call latencies,
+    /// 0 with no data
+    fn latency_percentile(&self, percentile: u32) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        let mut sorted: Vec<u64> = self.latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (percentile as usize * sorted.len()).div_ceil(100);
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    /// Forgive the oldest accumulated failure, flooring at zero
+    fn decay_failure(&mut self) {
+        if self.window_ms > 0 {
+            self.failure_times.pop_front();
+            self.sync_windowed_failures();
+        } else {
+            self.failure_count = self.failure_count.saturating_sub(1);
+            self.failure_score = self.failure_score.saturating_sub(1000);
+        }
+    }
+
+    /// Drop failures that fell out of the window, then count this one. A
+    /// failure's weight expires with it. Only the newest entries whose
+    /// weights reach `failure_threshold` can affect the decision, so older
+    /// ones are discarded to keep memory bounded.
+    fn record_windowed_failure(&mut self, weight_milli: u64, current_time_ms: u64) {
+        self.compact(current_time_ms);
+        self.failure_times.push_back((current_time_ms, weight_milli));
+        let needed = match self.trip_mode {
+            // The adaptive threshold moves with volume, so keep the whole
+            // window, up to `max_window_entries`
+            TripMode::Adaptive { .. } => u64::MAX,
+            _ => self.failure_threshold.max(1) as u64 * 1000,
+        };
+        let mut score: u64 = self.failure_times.iter().map(|&(_, w)| w).sum();
+        while let Some(&(_, oldest)) = self.failure_times.front() {
+            if score - oldest < needed {
+                break;
+            }
+            score -= oldest;
+            self.failure_times.pop_front();
+        }
+        self.cap_windows();
+        self.sync_windowed_failures();
+    }
+
+    /// Drop the oldest failure and call times beyond `max_window_entries`,
+    /// flagging the window as truncated if any had to go
+    fn cap_windows(&mut self) {
+        let cap = self.max_window_entries.max(1) as usize;
+        let excess_failures = self.failure_times.len().saturating_sub(cap);
+        let excess_calls = self.call_times.len().saturating_sub(cap);
+        if excess_failures + excess_calls > 0 {
+            self.failure_times.drain(..excess_failures);
+            self.call_times.drain(..excess_calls);
+            self.window_truncated = true;
+        }
+    }
+
+    /// Drop failures that fell out of the window by `current_time_ms`, so
+    /// the reported failure count reflects the window without waiting for
+    /// the next failure
+    fn compact(&mut self, current_time_ms: u64) {
+        if self.window_ms == 0 {
+            return;
+        }
+        let cutoff = current_time_ms.saturating_sub(self.window_ms);
+        let before = self.failure_times.len();
+        while self.failure_times.front().is_some_and(|&(t, _)| t < cutoff) {
+            self.failure_times.pop_front();
+        }
+        if self.failure_times.len() != before {
+            // Anything the cap dropped was older than what just expired
+            self.window_truncated = false;
+            self.sync_windowed_failures();
+        }
+    }
+
+    fn sync_windowed_failures(&mut self) {
+        self.failure_count = self.failure_times.len() as u32;
+        self.failure_score = self.failure_times.iter().map(|&(_, w)| w).sum();
+    }
+
+    /// Hand a forced breaker back to automatic recovery, timing recovery
+    /// from `current_time_ms`
+    fn release_force_open(&mut self, current_time_ms: u64) -> bool {
+        self.observe_time(current_time_ms);
+        if !self.forced_open {
+            return false;
+        }
+        self.forced_open = false;
+        if self.state == BreakerState::Open {
+            self.last_failure_time = Some(current_time_ms);
+        }
+        true
+    }
+
+    fn force_open(&mut self, current_time_ms: u64) {
+        self.observe_time(current_time_ms);
+        self.trip();
+        self.forced_open = true;
+        self.last_transition_cause = Some(TransitionCause::ManualForce);
+        self.last_failure_time = Some(current_time_ms);
+        self.state_changed_at = Some(current_time_ms);
+    }
+
+    /// Allow a request and hand out a token for `guard_end`, or
+    /// `GUARD_DENIED` if the request is rejected
+    fn guard_begin(&mut self, current_time_ms: u64) -> u32 {
+        if !self.allow_request(current_time_ms) {
+            return GUARD_DENIED;
+        }
+        let token = self.next_guard_token;
+        self.next_guard_token = match token.wrapping_add(1) {
+            GUARD_DENIED => 1,
+            next => next,
+        };
+        self.outstanding_guards.push_back(token);
+        if self.outstanding_guards.len() > MAX_OUTSTANDING_GUARDS {
+            self.outstanding_guards.pop_front();
+        }
+        token
+    }
+
+    /// Record the outcome for a token from `guard_begin`. Unknown,
+    /// already-ended, and previous-generation tokens are ignored.
+    fn guard_end(&mut self, token: u32, success: bool, current_time_ms: u64) -> bool {
+        let Some(pos) = self.outstanding_guards.iter().position(|&t| t == token) else {
+            return false;
+        };
+        self.outstanding_guards.remove(pos);
+        if success {
+            self.observe_time(current_time_ms);
+            self.record_success();
+        } else {
+            self.record_failure(current_time_ms);
+        }
+        true
+    }
+
+    fn force_half_open(&mut self, current_time_ms: u64) {
+        self.observe_time(current_time_ms);
+        if self.state == BreakerState::Open && !self.latched {
+            self.enter_half_open();
+            self.last_transition_cause = Some(TransitionCause::ManualForce);
+        }
+    }
+
+    /// Close the breaker without probing, as when the dependency is known
+    /// to be healthy. Only the runtime failure and success counts start
+    /// over; configuration, lifetime metrics, metadata and the backoff
+    /// level are kept. Unlike a recovery through probes this starts no
+    /// slow-start ramp or probation, so traffic is admitted in full.
+    fn force_close(&mut self, current_time_ms: u64) {
+        self.observe_time(current_time_ms);
+        if self.state != BreakerState::Closed {
+            self.set_state(BreakerState::Closed);
+            self.last_transition_cause = Some(TransitionCause::ManualClose);
+        }
+        self.forced_open = false;
+        self.latched = false;
+        self.penalized = false;
+        self.broken_since = None;
+        self.closed_at = None;
+        self.half_open_calls = 0;
+        self.probe_failures = 0;
+        self.probe_granted_at.clear();
+        self.failure_count = 0;
+        self.failure_score = 0;
+        self.success_count = 0;
+        self.failure_times.clear();
+        self.outcomes.clear();
+        self.call_times.clear();
+        self.calls_since_closed = 0;
+        self.healthy_streak = 0;
+    }
+
+    fn reset(&mut self) {
+        self.reset_runtime();
+        self.state_changed_at = Some(self.clock_ms);
+    }
+
+    /// Return to Closed and clear all runtime state: counters, failure,
+    /// outcome and latency windows, backoff level, jitter, slow-start ramp,
+    /// shedding and probe admission credit, and guard tokens. Configuration
+    /// and lifetime metrics are left untouched.
+    fn reset_runtime(&mut self) {
+        if self.state != BreakerState::Closed {
+            self.set_state(BreakerState::Closed);
+            self.last_transition_cause = Some(TransitionCause::Reset);
+        }
+        self.failure_count = 0;
+        self.failure_score = 0;
+        self.success_count = 0;
+        self.half_open_calls = 0;
+        self.probe_failures = 0;
+        self.probe_granted_at.clear();
+        self.last_failure_time = None;
+        self.failure_times.clear();
+        self.outcomes.clear();
+        self.call_times.clear();
+        self.consecutive_trips = 0;
+        self.jitter_ms = 0;
+        self.calls_since_closed = 0;
+        self.healthy_streak = 0;
+        self.config_changed_at = None;
+        self.outstanding_guards.clear();
+        self.ramp_started_at = None;
+        self.ramp_credit = 0;
+        self.shed_credit = 0;
+        self.probe_credit = 0;
+        self.latencies.clear();
+        self.pre_change_score = 0;
+        self.last_probe_time = None;
+        self.broken_since = None;
+        self.latched = false;
+        self.forced_open = false;
+        self.closed_at = None;
+        self.transition_times.clear();
+        self.penalized = false;
+        self.window_truncated = false;
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("breaker state is always serializable")
+    }
+
+    fn from_json(json: &str) -> Option<Self> {
+        Self::from_value(serde_json::from_str(json).ok()?)
+    }
+
+    /// Decode an exported state. Missing fields take their defaults, but
+    /// the value must at least carry a `state` and hold a configuration
+    /// the setters would accept, so a stray object isn't taken for one.
+    fn from_value(value: serde_json::Value) -> Option<Self> {
+        value.get("state")?;
+        let state: Self = serde_json::from_value(value).ok()?;
+        state.validate().ok()?;
+        Some(state)
+    }
+
+    /// Parse a test fixture. Unlike `from_json`, unknown fields are
+    /// rejected so a typo can't silently fall back to a default, and the
+    /// configuration must be one the setters would accept.
+    fn from_snapshot(json: &str) -> Result<Self, BreakerError> {
+        let invalid = |e: serde_json::Error| BreakerError::InvalidConfig(e.to_string());
+        let value: serde_json::Value = serde_json::from_str(json).map_err(invalid)?;
+        let known = serde_json::to_value(Self::default()).map_err(invalid)?;
+        if let (Some(fields), Some(known)) = (value.as_object(), known.as_object()) {
+            if let Some(field) = fields.keys().find(|k| !known.contains_key(*k)) {
+                return Err(BreakerError::InvalidConfig(format!(
+                    "unknown snapshot field `{}`",
+                    field
+                )));
+            }
+        }
+        let state: Self = serde_json::from_value(value).map_err(invalid)?;
+        state.validate()?;
+        Ok(state)
+    }
+
+    fn validate(&self) -> Result<(), BreakerError> {
+        let invalid = |msg: &str| Err(BreakerError::InvalidConfig(msg.to_string()));
+        if self.failure_threshold == 0 {
+            return invalid("failure_threshold must be at least 1");
+        }
+        if self.half_open_max_calls == 0 || self.half_open_success_threshold == 0 {
+            return invalid("half-open limits must be at least 1");
+        }
+        if self.jitter_percent > 50 {
+            return invalid("jitter_percent must be between 0 and 50");
+        }
+        if self.shed_percent > 100 {
+            return invalid("shed percent must be between 0 and 100");
+        }
+        if self.half_open_start_percent > 100 || self.half_open_step_percent > 100 {
+            return invalid("traffic ramp percentages must be between 0 and 100");
+        }
+        if !(1..=100).contains(&self.slow_call_rate_percent) {
+            return invalid("slow_call_rate_percent must be between 1 and 100");
+        }
+        if self.close_mode == CloseMode::Quorum
+            && self.half_open_success_threshold > self.half_open_max_calls
+        {
+            return invalid("quorum needs no more successes than probes");
+        }
+        if let TripMode::Rate { failure_rate_percent, minimum_calls } = self.trip_mode {
+            if !(1..=100).contains(&failure_rate_percent) {
+                return invalid("failure_rate_percent must be between 1 and 100");
+            }
+            if minimum_calls == 0 {
+                return invalid("minimum_calls must be at least 1");
+            }
+        }
+        if self.metadata.len() > MAX_METADATA_KEYS {
+            return invalid("too many metadata keys");
+        }
+        if let TripMode::CountWindow { window_size } = self.trip_mode {
+            if window_size == 0 || self.failure_threshold > window_size {
+                return invalid("count window must hold failure_threshold outcomes");
+            }
+        }
+        if let TripMode::Adaptive { k_milli } = self.trip_mode {
+            if k_milli == 0 {
+                return invalid("adaptive k must be positive");
+            }
+            if self.window_ms == 0 {
+                return invalid("adaptive mode needs a sliding window");
+            }
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            state: self.state.as_str(),
+            failure_count: self.failure_count,
+            success_count: self.success_count,
+            failure_threshold: self.failure_threshold,
+            recovery_timeout: self.recovery_timeout_ms as f64 / 1000.0,
+            recovery_timeout_ms: self.recovery_timeout_ms,
+            last_failure_time: self.last_failure_time,
+            trip_count: self.trip_count,
+            rejected_count: self.rejected_count,
+            state_changed_at: self.state_changed_at,
+            last_transition_cause: self.last_transition_cause.map(TransitionCause::as_str),
+            ignored_failures: self.ignored_failures,
+            shadow_mode: self.shadow_mode,
+            short_circuited_count: self.short_circuited_count,
+            evaluations_total: self.evaluations_total,
+            latched: self.latched,
+            forced_open: self.forced_open,
+            failure_score: self.failure_score as f64 / 1000.0,
+            enabled: self.enabled,
+            half_open_calls: self.half_open_calls,
+            half_open_max: self.half_open_max_calls,
+            timeout_count: self.timeout_count,
+            error_count: self.error_count,
+            metadata: self.metadata.clone(),
+            quiesced: self.quiesced,
+            window_truncated: self.window_truncated,
+        }
+    }
+
+    fn reset_metrics(&mut self) {
+        self.trip_count = 0;
+        self.trip_times.clear();
+        self.rejected_count = 0;
+        self.total_successes = 0;
+        self.total_failures = 0;
+        self.ignored_failures = 0;
+        self.short_circuited_count = 0;
+        self.evaluations_total = 0;
+        self.timeout_count = 0;
+        self.error_count = 0;
+        self.closed_dwell_ms = 0;
+        self.open_dwell_ms = 0;
+        self.half_open_dwell_ms = 0;
+        self.dwell_since = None;
+    }
+
+    fn status_json(&self) -> String {
+        let status = self.status();
+        let json = StatusJson {
+            failures: status.failure_count,
+            successes: status.success_count,
+            status: &status,
+        };
+        serde_json::to_string(&json).expect("status is always serializable")
+    }
+
+    fn config(&self) -> Config {
+        let (trip_mode, failure_rate_percent, rate_minimum_calls) = match self.trip_mode {
+            TripMode::Count => ("count", None, None),
+            TripMode::Rate { failure_rate_percent, minimum_calls } => {
+                ("rate", Some(failure_rate_percent), Some(minimum_calls))
+            }
+            TripMode::Adaptive { .. } => ("adaptive", None, None),
+            TripMode::CountWindow { .. } => ("count_window", None, None),
+        };
+        let count_window_size = match self.trip_mode {
+            TripMode::CountWindow { window_size } => Some(window_size),
+            _ => None,
+        };
+        let adaptive_k = match self.trip_mode {
+            TripMode::Adaptive { k_milli } => Some(k_milli as f64 / 1000.0),
+            _ => None,
+        };
+        Config {
+            failure_threshold: self.failure_threshold,
+            recovery_timeout_ms: self.recovery_timeout_ms,
+            half_open_max_calls: self.half_open_max_calls,
+            half_open_success_threshold: self.half_open_success_threshold,
+            window_ms: self.window_ms,
+            trip_mode,
+            failure_rate_percent,
+            rate_minimum_calls,
+            adaptive_k,
+            count_window_size,
+            minimum_calls: self.minimum_calls,
+            max_recovery_timeout_ms: self.max_recovery_timeout_ms,
+            jitter_percent: self.jitter_percent,
+            slow_call_threshold_ms: self.slow_call_threshold_ms,
+            slow_call_rate_percent: self.slow_call_rate_percent,
+            ramp_ms: self.ramp_ms,
+            probe_interval_ms: self.probe_interval_ms,
+            max_open_duration_ms: self.max_open_duration_ms,
+            decay_on_success: self.decay_on_success,
+            shadow_mode: self.shadow_mode,
+        }
+    }
+}
+
+/// Leading byte of `export_state_bytes` output; bump it whenever the field
+/// list in `binary_layout!` changes so old snapshots are rejected cleanly
+const BINARY_LAYOUT_VERSION: u8 = 22;
+
+/// Fixed little-endian encoding used by `export_state_bytes`
+trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Some(head)
+}
+
+impl Codec for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(take(input, 1)?[0])
+    }
+}
+
+impl Codec for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(u32::from_le_bytes(take(input, 4)?.try_into().ok()?))
+    }
+}
+
+impl Codec for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(u64::from_le_bytes(take(input, 8)?.try_into().ok()?))
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => false.encode(out),
+            Some(value) => {
+                true.encode(out);
+                value.encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        if bool::decode(input)? {
+            Some(Some(T::decode(input)?))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = u32::decode(input)? as usize;
+        String::from_utf8(take(input, len)?.to_vec()).ok()
+    }
+}
+
+/// Length-prefixed sequences
+fn encode_seq<'a, T: Codec + 'a>(items: impl ExactSizeIterator<Item = &'a T>, out: &mut Vec<u8>) {
+    (items.len() as u32).encode(out);
+    for item in items {
+        item.encode(out);
+    }
+}
+
+fn decode_seq<T: Codec, C: FromIterator<T>>(input: &mut &[u8]) -> Option<C> {
+    let len = u32::decode(input)?;
+    (0..len).map(|_| T::decode(input)).collect()
+}
+
+impl<T: Codec> Codec for VecDeque<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_seq(self.iter(), out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        decode_seq(input)
+    }
+}
+
+impl Codec for BTreeSet<String> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_seq(self.iter(), out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        decode_seq(input)
+    }
+}
+
+impl Codec for BTreeMap<String, String> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        decode_seq::<(String, String), _>(input)
+    }
+}
+
+impl Codec for TransitionCause {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        [
+            TransitionCause::FailureThreshold,
+            TransitionCause::HalfOpenFailure,
+            TransitionCause::ManualForce,
+            TransitionCause::RecoveryTimeout,
+            TransitionCause::RecoveryConfirmed,
+            TransitionCause::LateSuccess,
+            TransitionCause::ProbesSucceeded,
+            TransitionCause::Reset,
+            TransitionCause::ManualClose,
+            TransitionCause::ColdStart,
+        ]
+        .get(u8::decode(input)? as usize)
+        .copied()
+    }
+}
+
+impl Codec for BreakerState {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to_code().encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        BreakerState::from_code(u8::decode(input)?)
+    }
+}
+
+impl Codec for Outcome {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Outcome::Success => 0,
+            Outcome::SlowSuccess => 1,
+            Outcome::Failure => 2,
+        };
+        tag.encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Outcome::Success),
+            1 => Some(Outcome::SlowSuccess),
+            2 => Some(Outcome::Failure),
+            _ => None,
+        }
+    }
+}
+
+impl Codec for CloseMode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self == CloseMode::Quorum).encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        if bool::decode(input)? {
+            Some(CloseMode::Quorum)
+        } else {
+            Some(CloseMode::Consecutive)
+        }
+    }
+}
+
+impl Codec for TripMode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            TripMode::Count => 0u8.encode(out),
+            TripMode::Rate { failure_rate_percent, minimum_calls } => {
+                1u8.encode(out);
+                failure_rate_percent.encode(out);
+                minimum_calls.encode(out);
+            }
+            TripMode::Adaptive { k_milli } => {
+                2u8.encode(out);
+                k_milli.encode(out);
+            }
+            TripMode::CountWindow { window_size } => {
+                3u8.encode(out);
+                window_size.encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(TripMode::Count),
+            1 => Some(TripMode::Rate {
+                failure_rate_percent: u32::decode(input)?,
+                minimum_calls: u32::decode(input)?,
+            }),
+            2 => Some(TripMode::Adaptive { k_milli: u32::decode(input)? }),
+            3 => Some(TripMode::CountWindow { window_size: u32::decode(input)? }),
+            _ => None,
+        }
+    }
+}
+
+/// Lists the fields in `export_state_bytes` order. Like export/import,
+/// queued and logged transitions are not persisted.
+macro_rules! binary_layout {
+    ($($field:ident),* $(,)?) => {
+        impl CircuitBreakerState {
+            fn to_bytes(&self) -> Vec<u8> {
+                let mut out = vec![BINARY_LAYOUT_VERSION];
+                $(self.$field.encode(&mut out);)*
+                out
+            }
+
+            fn from_bytes(mut input: &[u8]) -> Option<Self> {
+                if u8::decode(&mut input)? != BINARY_LAYOUT_VERSION {
+                    return None;
+                }
+                let mut state = Self::default();
+                $(state.$field = Codec::decode(&mut input)?;)*
+                if !input.is_empty() {
+                    return None;
+                }
+                state.validate().ok()?;
+                Some(state)
+            }
+        }
+    };
+}
+
+binary_layout!(
+    state,
+    failure_count,
+    success_count,
+    failure_threshold,
+    recovery_timeout_ms,
+    last_failure_time,
+    half_open_calls,
+    half_open_max_calls,
+    half_open_success_threshold,
+    window_ms,
+    failure_times,
+    failure_score,
+    trip_mode,
+    close_mode,
+    probe_failures,
+    outcomes,
+    max_recovery_timeout_ms,
+    consecutive_trips,
+    jitter_percent,
+    jitter_ms,
+    rng_state,
+    clock_ms,
+    event_log_size,
+    slow_call_threshold_ms,
+    slow_call_rate_percent,
+    minimum_calls,
+    calls_since_closed,
+    outstanding_guards,
+    next_guard_token,
+    trip_count,
+    rejected_count,
+    total_successes,
+    total_failures,
+    state_changed_at,
+    trip_categories,
+    ignored_failures,
+    ramp_ms,
+    ramp_started_at,
+    ramp_credit,
+    decay_on_success,
+    shadow_mode,
+    short_circuited_count,
+    probe_interval_ms,
+    last_probe_time,
+    max_open_duration_ms,
+    broken_since,
+    latched,
+    forced_open,
+    healthy_reset_successes,
+    healthy_reset_ms,
+    healthy_streak,
+    config_grace_ms,
+    config_changed_at,
+    pre_change_score,
+    timeout_count,
+    error_count,
+    shed_percent,
+    shed_credit,
+    half_open_start_percent,
+    half_open_step_percent,
+    probe_credit,
+    enabled,
+    latencies,
+    probation_ms,
+    closed_at,
+    call_times,
+    probe_timeout_ms,
+    probe_granted_at,
+    metadata,
+    recover_on_open_success,
+    closed_dwell_ms,
+    open_dwell_ms,
+    half_open_dwell_ms,
+    dwell_since,
+    seeded,
+    trip_times,
+    trip_history_size,
+    gated_recovery,
+    quorum_timeout_ms,
+    quiesced,
+    last_transition_cause,
+    clock_tolerance_ms,
+    single_flight,
+    half_open_stall_ms,
+    evaluations_total,
+    flap_threshold,
+    flap_window_ms,
+    penalty_ms,
+    transition_times,
+    penalized,
+    max_window_entries,
+    window_truncated,
+);
+
+/// One step of the xorshift64 generator behind jitter and seeded draws
+fn xorshift(mut x: u64) -> u64 {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+/// Map a random `u64` to [0, 1)
+fn unit_sample(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Unit of the host's timestamps, chosen with `set_time_unit`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimeUnit {
+    Seconds,
+    Millis,
+    Micros,
+}
+
+impl TimeUnit {
+    fn to_ms(self, value: u64) -> u64 {
+        match self {
+            TimeUnit::Seconds => value.saturating_mul(1000),
+            TimeUnit::Millis => value,
+            TimeUnit::Micros => value / 1000,
+        }
+    }
+}
+
+/// Normalize a host timestamp to milliseconds
+fn host_time_ms(value: u64) -> u64 {
+    TIME_UNIT.with(|unit| unit.get()).to_ms(value)
+}
+
+/// Normalize the timeout argument of the seconds-based init functions
+fn secs_to_ms(secs: u64) -> u64 {
+    secs.saturating_mul(1000)
+}
+
+/// Choose the unit of every `current_time_ms` argument: `"ms"` (the
+/// default), `"us"`, or `"s"`. Only those timestamp arguments change.
+/// Everything else keeps its documented unit whatever is set: timeouts
+/// documented as seconds stay seconds, settings named `*_ms` stay
+/// milliseconds, and every time a function returns (`time_until_retry`,
+/// `next_probe_time`, `recent_trips`, status and event timestamps) is in
+/// milliseconds, which is also how times are kept internally.
+#[wasm_bindgen]
+pub fn set_time_unit(unit: &str) -> Result<(), BreakerError> {
+    let unit = match unit {
+        "s" => TimeUnit::Seconds,
+        "ms" => TimeUnit::Millis,
+        "us" => TimeUnit::Micros,
+        other => {
+            return Err(BreakerError::InvalidConfig(format!(
+                "unknown time unit `{}`, expected \"ms\", \"us\" or \"s\"",
+                other
+            )))
+        }
+    };
+    TIME_UNIT.with(|slot| slot.set(unit));
+    Ok(())
+}
+
+per_breaker! {
+    /// Initialize the circuit breaker with custom thresholds.
+    ///
+    /// **This resets the breaker**: it returns to Closed and all counters are
+    /// zeroed, even if it is currently Open. Every setting, mode and metadata
+    /// entry from earlier calls returns to its default too, here and in the
+    /// other `init_*` functions; only the lifetime metrics cleared by
+    /// `reset_metrics` are kept. Use `configure_breaker` to change
+    /// thresholds on a live breaker without losing its state, or `try_init` to
+    /// refuse resetting an Open one. A reset from Open or HalfOpen is reported
+    /// to the transition callback and event log like any other state change.
+    ///
+    /// `recovery_timeout` is in **seconds**; see `init_breaker_ms` to pass
+    /// milliseconds, matching the `current_time_ms` arguments.
+    ///
+    /// A `failure_threshold` of 0 is raised to 1, which keeps this function's
+    /// original signature; the other functions that take a threshold,
+    /// including `try_init`, reject 0 instead.
+    #[named(init_breaker_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker(b, failure_threshold: u32, recovery_timeout: u64) {
+        b.configure(failure_threshold.max(1), secs_to_ms(recovery_timeout));
+    }
+
+    /// Like `init_breaker`, but recover by quorum to avoid flapping on an
+    /// intermittently healthy dependency: HalfOpen admits exactly `probes`
+    /// probes, waits for all of their results, and closes only if a majority
+    /// succeeded, otherwise re-opens. Early failures don't re-open the breaker.
+    #[named(init_breaker_quorum_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_quorum(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+        probes: u32,
+    ) -> Result<(), BreakerError> {
+        configure_quorum(b, failure_threshold, recovery_timeout, probes, probes / 2 + 1)
+    }
+
+    /// Like `init_breaker_quorum`, but close when at least
+    /// `close_ratio_percent` of the `min_probes` probes succeed, for services
+    /// that are a little flaky even when healthy (e.g. 4 of 5 at 80%)
+    #[named(init_breaker_ratio_close_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_ratio_close(
+        b,
+        min_probes: u32,
+        close_ratio_percent: u32,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        if close_ratio_percent == 0 || close_ratio_percent > 100 {
+            return Err(BreakerError::InvalidConfig(
+                "close_ratio_percent must be between 1 and 100".to_string(),
+            ));
+        }
+        let required = (min_probes as u64 * close_ratio_percent as u64).div_ceil(100) as u32;
+        configure_quorum(b, failure_threshold, recovery_timeout, min_probes, required)
+    }
+
+    /// Stop a quorum window (`init_breaker_quorum`, `init_breaker_ratio_close`)
+    /// from waiting forever on probes that never report. Once the breaker has
+    /// been HalfOpen for `timeout_ms`, the next request decides the window on
+    /// the results received so far: it closes if they meet the window's
+    /// success ratio (e.g. 2 of 2 reported for a 3-of-4 quorum), and re-opens
+    /// otherwise, including when no probe reported at all. 0 disables the
+    /// timeout.
+    #[named(set_quorum_timeout_named)]
+    #[wasm_bindgen]
+    pub fn set_quorum_timeout(b, timeout_ms: u64) {
+        b.quorum_timeout_ms = timeout_ms;
+    }
+
+    /// Accept failure timestamps up to `tolerance_ms` behind the last recorded
+    /// failure, as loosely synchronized clients produce, by treating them as
+    /// happening at that last failure. Failures further behind are ignored.
+    /// Defaults to 1000ms; 0 ignores any failure stamped in the past.
+    #[named(set_clock_tolerance_named)]
+    #[wasm_bindgen]
+    pub fn set_clock_tolerance(b, tolerance_ms: u64) {
+        b.clock_tolerance_ms = tolerance_ms;
+    }
+}
+
+/// Reject a failure threshold of 0, which would trip on any outcome, as
+/// `load_snapshot` does
+fn check_failure_threshold(failure_threshold: u32) -> Result<(), BreakerError> {
+    if failure_threshold == 0 {
+        return Err(BreakerError::InvalidConfig(
+            "failure_threshold must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn configure_quorum(
+    b: &mut CircuitBreakerState,
+    failure_threshold: u32,
+    recovery_timeout: u64,
+    probes: u32,
+    required_successes: u32,
+) -> Result<(), BreakerError> {
+    check_failure_threshold(failure_threshold)?;
+    if probes == 0 {
+        return Err(BreakerError::InvalidConfig(
+            "probes must be at least 1".to_string(),
+        ));
+    }
+    b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+    b.close_mode = CloseMode::Quorum;
+    b.half_open_max_calls = probes;
+    b.half_open_success_threshold = required_successes.max(1);
+    Ok(())
+}
+
+per_breaker! {
+    /// Like `init_breaker`, but refuse to reset an Open breaker so a
+    /// reconfiguration can't accidentally un-trip it. Returns false, leaving
+    /// the breaker untouched, if it is Open or `failure_threshold` is 0.
+    #[named(try_init_named)]
+    #[wasm_bindgen]
+    pub fn try_init(b, failure_threshold: u32, recovery_timeout: u64) -> bool {
+        if check_failure_threshold(failure_threshold).is_err() {
+            return false;
+        }
+        if b.state == BreakerState::Open {
+            return false;
+        }
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        true
+    }
+
+    /// Like `init_breaker`, but start in HalfOpen so only probe traffic flows
+    /// until the dependency proves itself with enough successes to close
+    #[named(init_breaker_cold_start_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_cold_start(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.enter_half_open();
+        b.last_transition_cause = Some(TransitionCause::ColdStart);
+        Ok(())
+    }
+
+    /// Update thresholds without touching the current state or counters, so
+    /// it is safe to call repeatedly. `recovery_timeout` is in seconds. Starts
+    /// the grace period set with `set_config_grace`.
+    #[named(configure_breaker_named)]
+    #[wasm_bindgen]
+    pub fn configure_breaker(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.reconfigure(failure_threshold, secs_to_ms(recovery_timeout));
+        Ok(())
+    }
+
+    /// For `grace_ms` after `configure_breaker`, only failures recorded since
+    /// the change count toward tripping, so tightening the threshold during an
+    /// incident doesn't open the breaker on the spot over old failures. 0
+    /// disables the grace period.
+    #[named(set_config_grace_named)]
+    #[wasm_bindgen]
+    pub fn set_config_grace(b, grace_ms: u64) {
+        b.config_grace_ms = grace_ms;
+    }
+
+    /// For `probation_ms` after recovering from HalfOpen, a single failure
+    /// reopens the breaker instead of waiting for the failure threshold, to
+    /// catch dependencies that relapse right after closing. 0 disables it.
+    #[named(set_probation_named)]
+    #[wasm_bindgen]
+    pub fn set_probation(b, probation_ms: u64) {
+        b.probation_ms = probation_ms;
+    }
+
+    /// Initialize the circuit breaker with a recovery timeout in milliseconds
+    #[named(init_breaker_ms_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_ms(
+        b,
+        failure_threshold: u32,
+        recovery_timeout_ms: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.configure(failure_threshold, recovery_timeout_ms);
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker, also setting the HalfOpen probe budget.
+    ///
+    /// `half_open_max` is used both as the number of probes `allow_request`
+    /// admits at once (not in total) while HalfOpen and as the success count
+    /// `record_success` needs to close; see `set_half_open_limits` to set them
+    /// separately. It must be at least 1, otherwise HalfOpen could never admit
+    /// a probe.
+    #[named(init_breaker_full_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_full(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+        half_open_max: u32,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        if half_open_max == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "half_open_max must be at least 1".to_string(),
+            ));
+        }
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.half_open_max_calls = half_open_max;
+        b.half_open_success_threshold = half_open_max;
+        Ok(())
+    }
+
+    /// Set the HalfOpen concurrency limit and close condition independently.
+    ///
+    /// `max_calls` caps how many probes may be in flight at once; a success
+    /// frees its slot. It limits concurrency, not the probes per HalfOpen
+    /// window: as probes succeed new ones are granted, until
+    /// `success_threshold` successes close the breaker (e.g. 1 and 5 probe one
+    /// at a time, five times). In quorum mode (`init_breaker_quorum`) slots
+    /// stay taken until the window is decided, so there it is the total.
+    /// Both must be at least 1.
+    #[named(set_half_open_limits_named)]
+    #[wasm_bindgen]
+    pub fn set_half_open_limits(
+        b,
+        max_calls: u32,
+        success_threshold: u32,
+    ) -> Result<(), BreakerError> {
+        check_half_open_limits(max_calls, success_threshold)?;
+        b.half_open_max_calls = max_calls;
+        b.half_open_success_threshold = success_threshold;
+        Ok(())
+    }
+}
+
+fn check_half_open_limits(max_calls: u32, success_threshold: u32) -> Result<(), BreakerError> {
+    if max_calls == 0 || success_threshold == 0 {
+        return Err(BreakerError::InvalidConfig(
+            "half-open limits must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+per_breaker! {
+    /// Initialize the circuit breaker, counting only failures from the last
+    /// `window_ms` milliseconds toward the threshold. A `window_ms` of 0
+    /// restores the plain cumulative count.
+    #[named(init_breaker_window_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_window(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+        window_ms: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.window_ms = window_ms;
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker to trip on failure rate instead of count.
+    ///
+    /// The breaker keeps the last `minimum_calls` outcomes and opens once the
+    /// share of failures among them reaches `failure_rate_percent`. It stays
+    /// Closed until `minimum_calls` outcomes have been recorded.
+    #[named(init_breaker_rate_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_rate(
+        b,
+        failure_rate_percent: u32,
+        minimum_calls: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        if failure_rate_percent == 0 || failure_rate_percent > 100 {
+            return Err(BreakerError::InvalidConfig(
+                "failure_rate_percent must be between 1 and 100".to_string(),
+            ));
+        }
+        if minimum_calls == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "minimum_calls must be at least 1".to_string(),
+            ));
+        }
+        b.configure(b.failure_threshold, secs_to_ms(recovery_timeout));
+        b.trip_mode = TripMode::Rate {
+            failure_rate_percent,
+            minimum_calls,
+        };
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker to trip once `failure_threshold` of the
+    /// last `window_size` calls failed (e.g. 5 of the last 20), regardless of
+    /// when they happened. Older outcomes drop out of the window as new ones
+    /// are recorded.
+    #[named(init_breaker_count_window_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_count_window(
+        b,
+        window_size: u32,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        if failure_threshold == 0 || failure_threshold > window_size {
+            return Err(BreakerError::InvalidConfig(
+                "failure_threshold must be between 1 and window_size".to_string(),
+            ));
+        }
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.trip_mode = TripMode::CountWindow { window_size };
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker with a threshold that scales with traffic.
+    ///
+    /// The breaker opens once the failures in the last `window_ms` exceed
+    /// `k * sqrt(calls)`, where `calls` counts every success and failure in
+    /// that window. With `k = 2` it tolerates 20 failures in 100 calls but 200
+    /// in 10,000, so quiet hours still trip on a handful of failures without
+    /// peak traffic tripping on noise.
+    #[named(init_breaker_adaptive_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_adaptive(
+        b,
+        k: f64,
+        window_ms: u64,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        if !(k.is_finite() && k > 0.0) {
+            return Err(BreakerError::InvalidConfig("k must be positive".to_string()));
+        }
+        if window_ms == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "window_ms must be at least 1".to_string(),
+            ));
+        }
+        let k_milli = ((k * 1000.0).round() as u32).max(1);
+        b.configure(b.failure_threshold, secs_to_ms(recovery_timeout));
+        b.window_ms = window_ms;
+        b.trip_mode = TripMode::Adaptive { k_milli };
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker to damp flapping. When a trip completes
+    /// `flap_threshold` state changes within `flap_window_ms` (e.g. repeated
+    /// Closed -> Open -> HalfOpen -> Closed cycles), that Open period lasts
+    /// at least `penalty_ms` instead of the normal recovery timeout. Counting
+    /// starts over after each penalty.
+    #[named(init_breaker_antiflap_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_antiflap(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+        flap_threshold: u32,
+        flap_window_ms: u64,
+        penalty_ms: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        if flap_threshold < 2 || flap_window_ms == 0 || penalty_ms == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "antiflap needs flap_threshold >= 2 and non-zero window and penalty".to_string(),
+            ));
+        }
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.flap_threshold = flap_threshold;
+        b.flap_window_ms = flap_window_ms;
+        b.penalty_ms = penalty_ms;
+        Ok(())
+    }
+
+    /// Initialize the circuit breaker to gate recovery on an external signal.
+    ///
+    /// Once `recovery_timeout` (seconds) has passed the breaker doesn't probe
+    /// on its own: it keeps denying requests as `open_awaiting_confirmation`
+    /// until `confirm_recovery` reports that a separate health check passed,
+    /// and only then moves to HalfOpen.
+    #[named(init_breaker_gated_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_gated(
+        b,
+        failure_threshold: u32,
+        recovery_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.configure(failure_threshold, secs_to_ms(recovery_timeout));
+        b.gated_recovery = true;
+        Ok(())
+    }
+
+    /// Move a gated breaker that has waited out its recovery timeout to
+    /// HalfOpen so probing can begin. Returns false, changing nothing, if the
+    /// breaker isn't gated, isn't Open, is latched, or is still within its
+    /// recovery timeout.
+    #[named(confirm_recovery_named)]
+    #[wasm_bindgen]
+    pub fn confirm_recovery(b, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.confirm_recovery(current_time_ms)
+    }
+
+    /// Initialize the circuit breaker with exponential recovery backoff.
+    ///
+    /// Each failed HalfOpen probe doubles the wait before the next probe,
+    /// starting from `base_timeout` and capped at `max_timeout` (both in
+    /// seconds). The backoff resets once the breaker closes again.
+    #[named(init_breaker_backoff_named)]
+    #[wasm_bindgen]
+    pub fn init_breaker_backoff(
+        b,
+        failure_threshold: u32,
+        base_timeout: u64,
+        max_timeout: u64,
+    ) -> Result<(), BreakerError> {
+        check_failure_threshold(failure_threshold)?;
+        b.configure(failure_threshold, secs_to_ms(base_timeout));
+        b.max_recovery_timeout_ms = secs_to_ms(max_timeout.max(base_timeout));
+        Ok(())
+    }
+
+    /// Randomly extend each recovery timeout by up to `jitter_percent` (0-50).
+    ///
+    /// WASM has no entropy source, so callers supply a `seed`, e.g. from
+    /// `Math.random()`. Breakers seeded differently that trip together will
+    /// move to HalfOpen at different times instead of probing in lockstep.
+    #[named(set_recovery_jitter_named)]
+    #[wasm_bindgen]
+    pub fn set_recovery_jitter(b, jitter_percent: u32, seed: u64) -> Result<(), BreakerError> {
+        configure_jitter(b, jitter_percent, seed)
+    }
+}
+
+fn configure_jitter(
+    b: &mut CircuitBreakerState,
+    jitter_percent: u32,
+    seed: u64,
+) -> Result<(), BreakerError> {
+    if jitter_percent > 50 {
+        return Err(BreakerError::InvalidConfig(
+            "jitter_percent must be between 0 and 50".to_string(),
+        ));
+    }
+    b.jitter_percent = jitter_percent;
+    b.seed_rng(seed);
+    Ok(())
+}
+
+per_breaker! {
+    /// Require at least `minimum_calls` recorded calls since the breaker last
+    /// closed before the failure threshold is evaluated. Failures still
+    /// accumulate below the minimum. Rate mode has its own `minimum_calls`
+    /// and ignores this setting; 0 disables the gate.
+    #[named(set_minimum_calls_named)]
+    #[wasm_bindgen]
+    pub fn set_minimum_calls(b, minimum_calls: u32) {
+        b.minimum_calls = minimum_calls;
+    }
+
+    /// Give up on recovery once the breaker has stayed broken (Open or failing
+    /// its probes, without closing) for `max_open_duration_ms`: it latches Open
+    /// and refuses even probes until `reset_breaker`. 0 disables the latch.
+    #[named(set_max_open_duration_named)]
+    #[wasm_bindgen]
+    pub fn set_max_open_duration(b, max_open_duration_ms: u64) {
+        b.max_open_duration_ms = max_open_duration_ms;
+    }
+
+    /// Bypass the breaker during incident response without losing its state.
+    /// While disabled every request is allowed and recorded outcomes are
+    /// ignored; re-enabling resumes from the state it had when disabled.
+    /// Unlike `reset_breaker`, nothing is cleared.
+    #[named(set_enabled_named)]
+    #[wasm_bindgen]
+    pub fn set_enabled(b, enabled: bool) {
+        b.enabled = enabled;
+    }
+
+    /// Recover gradually: instead of a fixed probe budget, HalfOpen admits
+    /// `start_percent` of traffic plus `step_percent` for each success so far,
+    /// up to 100%. The breaker still closes after the configured number of
+    /// successes. A `start_percent` of 0 restores the fixed budget.
+    #[named(set_half_open_traffic_ramp_named)]
+    #[wasm_bindgen]
+    pub fn set_half_open_traffic_ramp(
+        b,
+        start_percent: u32,
+        step_percent: u32,
+    ) -> Result<(), BreakerError> {
+        if start_percent > 100 || step_percent > 100 {
+            return Err(BreakerError::InvalidConfig(
+                "traffic ramp percentages must be between 0 and 100".to_string(),
+            ));
+        }
+        b.half_open_start_percent = start_percent;
+        b.half_open_step_percent = step_percent;
+        Ok(())
+    }
+
+    /// Carry the backoff level across a recovery until the breaker proves it
+    /// is healthy: `successes` in a row while Closed, or `healthy_reset_ms`
+    /// spent Closed, whichever comes first (0 disables either rule). Until
+    /// then a relapse continues the backoff where it left off. With both 0,
+    /// the default, closing resets the backoff level immediately.
+    #[named(set_healthy_reset_named)]
+    #[wasm_bindgen]
+    pub fn set_healthy_reset(b, successes: u32, healthy_reset_ms: u64) {
+        b.healthy_reset_successes = successes;
+        b.healthy_reset_ms = healthy_reset_ms;
+    }
+
+    /// Seed the breaker's pseudo-random generator and use it for every
+    /// randomized decision: recovery jitter, the slow-start and HalfOpen
+    /// traffic ramps, and load shedding. Without a seed those ramps and
+    /// shedding admit exactly every Nth request instead. Breakers seeded alike
+    /// decide alike, which makes tests reproducible; a sample passed to
+    /// `allow_request_sampled` still takes precedence.
+    #[named(seed_rng_named)]
+    #[wasm_bindgen]
+    pub fn seed_rng(b, seed: u64) {
+        b.seed_rng(seed);
+        b.seeded = true;
+    }
+
+    /// Shed `percent` of requests while the breaker is Closed, independent of
+    /// failure detection, e.g. as a safety valve during a canary rollout.
+    /// HalfOpen probes are never shed. 0 disables shedding.
+    #[named(set_shed_percent_named)]
+    #[wasm_bindgen]
+    pub fn set_shed_percent(b, percent: u32) -> Result<(), BreakerError> {
+        check_shed_percent(percent)?;
+        b.shed_percent = percent;
+        b.shed_credit = 0;
+        Ok(())
+    }
+}
+
+fn check_shed_percent(percent: u32) -> Result<(), BreakerError> {
+    if percent > 100 {
+        return Err(BreakerError::InvalidConfig(
+            "shed percent must be between 0 and 100".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+per_breaker! {
+    /// Space HalfOpen probes at least `probe_interval_ms` apart, so recovery is
+    /// judged over time rather than by a burst. The first probe of a HalfOpen
+    /// window is never delayed; 0 disables spacing.
+    #[named(set_probe_interval_named)]
+    #[wasm_bindgen]
+    pub fn set_probe_interval(b, probe_interval_ms: u64) {
+        b.probe_interval_ms = probe_interval_ms;
+    }
+
+    /// Free a HalfOpen probe slot if no success or failure is recorded within
+    /// `probe_timeout_ms` of granting it, so a caller that forgets to report a
+    /// probe can't stall recovery. The slot is freed without counting a
+    /// result; 0 disables the timeout.
+    #[named(set_probe_timeout_named)]
+    #[wasm_bindgen]
+    pub fn set_probe_timeout(b, probe_timeout_ms: u64) {
+        b.probe_timeout_ms = probe_timeout_ms;
+    }
+
+    /// Re-open a HalfOpen breaker whose probe slots have all been granted
+    /// without any reporting back for `stall_ms`, so a hung dependency can't
+    /// hold it in HalfOpen. The next request after the stall counts it as a
+    /// failed probe window: the Open period starts then and backs off as
+    /// usual. Unlike `set_probe_timeout`, which frees the slots for new
+    /// probes, this gives up on the window; 0 disables it.
+    #[named(set_half_open_stall_named)]
+    #[wasm_bindgen]
+    pub fn set_half_open_stall(b, stall_ms: u64) {
+        b.half_open_stall_ms = stall_ms;
+    }
+
+    /// Cap the sliding window buffers at `max_entries` timestamps each
+    /// (10000 by default), so a long `window_ms` on a busy breaker can't
+    /// exhaust memory. Past the cap the oldest entries are dropped even though
+    /// they are still inside the window, so the breaker undercounts failures
+    /// (and, in adaptive mode, calls) until they would have expired anyway;
+    /// status reports `window_truncated` while that is the case.
+    #[named(set_max_window_entries_named)]
+    #[wasm_bindgen]
+    pub fn set_max_window_entries(b, max_entries: u32) -> Result<(), BreakerError> {
+        if max_entries == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "max_entries must be at least 1".to_string(),
+            ));
+        }
+        b.max_window_entries = max_entries;
+        b.cap_windows();
+        if b.window_ms > 0 {
+            b.sync_windowed_failures();
+        }
+        Ok(())
+    }
+
+    /// Ramp traffic back up over `ramp_ms` after the breaker recovers to Closed.
+    ///
+    /// The admitted share rises linearly from 10% to 100%:
+    /// `permille = 100 + 900 * elapsed_ms / ramp_ms`. Admission is
+    /// deterministic: every request earns `permille` credit and one request is
+    /// admitted per 1000 credit, so at 10% exactly every 10th request passes.
+    /// A `ramp_ms` of 0 disables slow start.
+    #[named(set_slow_start_named)]
+    #[wasm_bindgen]
+    pub fn set_slow_start(b, ramp_ms: u64) {
+        b.ramp_ms = ramp_ms;
+        if ramp_ms == 0 {
+            b.ramp_started_at = None;
+        }
+    }
+
+    /// When enabled, each success while Closed reduces the accumulated failure
+    /// count by one (never below zero), so only a burst of failures trips the
+    /// breaker rather than sporadic ones spread over a long period.
+    #[named(set_decay_on_success_named)]
+    #[wasm_bindgen]
+    pub fn set_decay_on_success(b, enabled: bool) {
+        b.decay_on_success = enabled;
+    }
+
+    /// Allow a single HalfOpen probe in flight at a time, for dependencies
+    /// too expensive to probe in parallel. While one is outstanding further
+    /// requests are denied, whatever `half_open_max_calls` allows; the next
+    /// probe is granted once its success or failure is recorded (or
+    /// `probe_timeout_ms` reclaims it). The probe budget still caps the total
+    /// number of probes in the window.
+    #[named(set_single_flight_named)]
+    #[wasm_bindgen]
+    pub fn set_single_flight(b, enabled: bool) {
+        b.single_flight = enabled;
+    }
+
+    /// Choose how a success recorded while Open is handled. Requests are
+    /// denied while Open, so such a success comes from a call admitted before
+    /// the trip whose result arrived late.
+    ///
+    /// - false (the default): it only counts toward `total_successes`
+    /// - true: it is taken as evidence of recovery and moves the breaker to
+    ///   HalfOpen without waiting for the recovery timeout, so probes can
+    ///   confirm it. A latched breaker stays Open.
+    #[named(set_recover_on_open_success_named)]
+    #[wasm_bindgen]
+    pub fn set_recover_on_open_success(b, enabled: bool) {
+        b.recover_on_open_success = enabled;
+    }
+
+    /// Dry-run mode: `allow_request` always returns true, while the state
+    /// machine keeps transitioning and counting so `get_status` shows what the
+    /// breaker would have done. Useful for tuning thresholds on live traffic.
+    #[named(set_shadow_mode_named)]
+    #[wasm_bindgen]
+    pub fn set_shadow_mode(b, enabled: bool) {
+        b.shadow_mode = enabled;
+    }
+}
+
+/// Fluent configuration for a breaker, as an alternative to the `init_*`
+/// functions:
+///
+/// ```js
+/// new BreakerBuilder().with_failure_threshold(3).with_window_ms(10000n).build();
+/// ```
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct BreakerBuilder {
+    failure_threshold: u32,
+    recovery_timeout_ms: u64,
+    half_open_max: u32,
+    window_ms: u64,
+}
+
+impl Default for BreakerBuilder {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            recovery_timeout_ms: 60_000,
+            half_open_max: 3,
+            window_ms: 0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl BreakerBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> BreakerBuilder {
+        Self::default()
+    }
+
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> BreakerBuilder {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    pub fn with_recovery_timeout_ms(mut self, recovery_timeout_ms: u64) -> BreakerBuilder {
+        self.recovery_timeout_ms = recovery_timeout_ms;
+        self
+    }
+
+    /// Sets both the HalfOpen probe limit and the successes needed to close
+    pub fn with_half_open_max(mut self, half_open_max: u32) -> BreakerBuilder {
+        self.half_open_max = half_open_max;
+        self
+    }
+
+    pub fn with_window_ms(mut self, window_ms: u64) -> BreakerBuilder {
+        self.window_ms = window_ms;
+        self
+    }
+
+    /// Install this configuration on the default breaker, resetting it
+    pub fn build(&self) -> Result<(), BreakerError> {
+        self.validate()?;
+        with_default_breaker(|b| self.apply(b));
+        Ok(())
+    }
+
+    /// Install this configuration on the named breaker, creating it if needed
+    pub fn build_named(&self, name: &str) -> Result<(), BreakerError> {
+        self.validate()?;
+        with_registry(|breakers| self.apply(breakers.entry(name.to_string()).or_default()));
+        Ok(())
+    }
+}
+
+impl BreakerBuilder {
+    fn validate(&self) -> Result<(), BreakerError> {
+        check_failure_threshold(self.failure_threshold)?;
+        if self.half_open_max == 0 {
+            return Err(BreakerError::InvalidConfig(
+                "half_open_max must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, b: &mut CircuitBreakerState) {
+        b.configure(self.failure_threshold, self.recovery_timeout_ms);
+        b.half_open_max_calls = self.half_open_max;
+        b.half_open_success_threshold = self.half_open_max;
+        b.window_ms = self.window_ms;
+    }
+}
+
+per_breaker! {
+    /// Check if a request should be allowed
+    #[named(allow_request_named)]
+    #[wasm_bindgen]
+    pub fn allow_request(b, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.allow_request(current_time_ms)
+    }
+
+    /// Like `allow_request`, but explains the decision as
+    /// `{ allowed, state, reason }`, e.g. to tell an Open rejection (retry
+    /// after `time_until_retry`) from an exhausted probe budget
+    #[named(evaluate_request_named)]
+    #[wasm_bindgen(unchecked_return_type = "RequestDecision")]
+    pub fn evaluate_request(b, current_time_ms: u64) -> Result<JsValue, JsValue> {
+        let current_time_ms = host_time_ms(current_time_ms);
+        to_js(&b.evaluate_request(current_time_ms))
+    }
+
+    /// `allow_request` with a caller-supplied random `sample` in [0, 1) for
+    /// the HalfOpen traffic ramp and load shedding, since WASM has no RNG of
+    /// its own. Otherwise it behaves exactly like `allow_request`.
+    #[named(allow_request_sampled_named)]
+    #[wasm_bindgen]
+    pub fn allow_request_sampled(b, current_time_ms: u64, sample: f64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.evaluate_request_sampled(current_time_ms, Some(sample), 1).allowed
+    }
+
+    /// `allow_request` for a request that takes `cost` HalfOpen probe slots,
+    /// so an expensive operation uses more of the recovery budget than a cheap
+    /// one. It is denied while HalfOpen unless `cost` slots are free, and its
+    /// success or failure frees them all. A `cost` of 0 counts as 1.
+    #[named(allow_request_weighted_named)]
+    #[wasm_bindgen]
+    pub fn allow_request_weighted(b, cost: u32, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.evaluate_request_sampled(current_time_ms, None, cost.max(1)).allowed
+    }
+
+    /// Check if a request would be allowed, without side effects: the answer
+    /// `allow_request` would give, including load shedding and shadow mode,
+    /// without using up shedding credit or a seeded draw
+    #[named(would_allow_named)]
+    #[wasm_bindgen]
+    pub fn would_allow(b, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.would_allow(current_time_ms)
+    }
+
+    /// Whether a HalfOpen probe slot is free (always true when Closed, false
+    /// when Open). Does not consume the slot.
+    #[named(probe_available_named)]
+    #[wasm_bindgen]
+    pub fn probe_available(b) -> bool {
+        b.probe_available()
+    }
+
+    /// How many probe requests could be dispatched at once right now. Does not
+    /// consume any slots.
+    #[named(probe_slots_remaining_named)]
+    #[wasm_bindgen]
+    pub fn probe_slots_remaining(b) -> u32 {
+        b.probe_slots_remaining()
+    }
+
+    /// Milliseconds until the breaker will next admit a probe, or `undefined`
+    /// when it is not Open. Reflects the current backoff level and jitter.
+    #[named(time_until_retry_named)]
+    #[wasm_bindgen]
+    pub fn time_until_retry(b, current_time_ms: u64) -> Option<u64> {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.time_until_retry(current_time_ms)
+    }
+
+    /// Absolute time at which the next request or HalfOpen probe will be
+    /// admitted, counting the recovery timeout, backoff, jitter and probe
+    /// spacing, so callers can `setTimeout` to it instead of polling. Returns
+    /// the current time when a request would be admitted now, and `undefined`
+    /// when the breaker is latched Open or its probes are all outstanding.
+    #[named(next_probe_time_named)]
+    #[wasm_bindgen]
+    pub fn next_probe_time(b, current_time_ms: u64) -> Option<u64> {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.next_probe_time(current_time_ms)
+    }
+
+    /// Recovery timeout in milliseconds that the next Open->HalfOpen
+    /// transition will use, reflecting backoff and jitter. Returns the base
+    /// timeout while Closed.
+    #[named(current_recovery_timeout_ms_named)]
+    #[wasm_bindgen]
+    pub fn current_recovery_timeout_ms(b) -> u64 {
+        b.current_recovery_timeout_ms()
+    }
+
+    /// Begin a guarded call: returns a nonzero token if the request is allowed,
+    /// or 0 if it is denied. Pass the token to `guard_end` when the call finishes.
+    #[named(guard_begin_named)]
+    #[wasm_bindgen]
+    pub fn guard_begin(b, current_time_ms: u64) -> u32 {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.guard_begin(current_time_ms)
+    }
+
+    /// Finish a guarded call, recording its outcome. Returns false if the token
+    /// is unknown, already ended, or predates the breaker's last state change.
+    #[named(guard_end_named)]
+    #[wasm_bindgen]
+    pub fn guard_end(b, token: u32, success: bool, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.guard_end(token, success, current_time_ms)
+    }
+
+    /// Number of guarded calls begun but not yet ended in the current state,
+    /// e.g. to detect abandoned HalfOpen probes
+    #[named(outstanding_guards_named)]
+    #[wasm_bindgen]
+    pub fn outstanding_guards(b) -> u32 {
+        b.outstanding_guards.len() as u32
+    }
+
+    /// Record a successful operation
+    #[named(record_success_named)]
+    #[wasm_bindgen]
+    pub fn record_success(b) {
+        b.record_success();
+    }
+
+    /// Record a failed operation
+    #[named(record_failure_named)]
+    #[wasm_bindgen]
+    pub fn record_failure(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_failure(current_time_ms);
+    }
+
+    /// Record a success and return the resulting `get_status` JSON in one call
+    #[named(record_success_and_status_named)]
+    #[wasm_bindgen]
+    pub fn record_success_and_status(b) -> String {
+        b.record_success();
+        b.status_json()
+    }
+
+    /// Record a failure and return the resulting `get_status` JSON, including
+    /// any transition the failure caused, in one call
+    #[named(record_failure_and_status_named)]
+    #[wasm_bindgen]
+    pub fn record_failure_and_status(b, current_time_ms: u64) -> String {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_failure(current_time_ms);
+        b.status_json()
+    }
+
+    /// Record a failed call that timed out. It counts toward tripping exactly
+    /// like `record_failure`, but is reported as `timeout_count` rather than
+    /// `error_count`.
+    #[named(record_timeout_named)]
+    #[wasm_bindgen]
+    pub fn record_timeout(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_failure_kind(FailureKind::Timeout, 1000, current_time_ms);
+    }
+
+    /// Record a failure that should open the breaker immediately, e.g. revoked
+    /// credentials or 501 Not Implemented
+    #[named(record_fatal_failure_named)]
+    #[wasm_bindgen]
+    pub fn record_fatal_failure(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_fatal_failure(current_time_ms);
+    }
+
+    /// Record a failure that counts `weight` toward `failure_threshold`
+    /// instead of 1, e.g. 2.0 for a refused connection against 1.0 for a
+    /// timeout. Weights are kept to thousandths and must be positive. With a
+    /// sliding window, a failure's weight leaves the score when it ages out.
+    /// Rate mode counts every failure once, whatever its weight.
+    #[named(record_failure_weighted_named)]
+    #[wasm_bindgen]
+    pub fn record_failure_weighted(
+        b,
+        weight: f64,
+        current_time_ms: u64,
+    ) -> Result<(), BreakerError> {
+        let current_time_ms = host_time_ms(current_time_ms);
+        if !(weight.is_finite() && weight > 0.0) {
+            return Err(BreakerError::InvalidConfig(
+                "failure weight must be a positive number".to_string(),
+            ));
+        }
+        let weight_milli = ((weight * 1000.0).round() as u64).max(1);
+        b.record_failure_weighted(weight_milli, current_time_ms);
+        Ok(())
+    }
+
+    /// Record a completed call along with how long it took
+    #[named(record_result_named)]
+    #[wasm_bindgen]
+    pub fn record_result(b, success: bool, latency_ms: u64, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_result(success, latency_ms, current_time_ms);
+    }
+
+    /// Trip the breaker when too many successful calls are slow.
+    ///
+    /// Calls recorded through `record_result` taking at least
+    /// `slow_call_threshold_ms` count as slow; once they make up
+    /// `slow_call_rate_percent` of the outcome window the breaker opens. The
+    /// window is the failure-rate window in rate mode, otherwise the last 100
+    /// calls. A threshold of 0 disables slow-call detection.
+    #[named(set_slow_call_threshold_named)]
+    #[wasm_bindgen]
+    pub fn set_slow_call_threshold(
+        b,
+        slow_call_threshold_ms: u64,
+        slow_call_rate_percent: u32,
+    ) -> Result<(), BreakerError> {
+        if slow_call_rate_percent == 0 || slow_call_rate_percent > 100 {
+            return Err(BreakerError::InvalidConfig(
+                "slow_call_rate_percent must be between 1 and 100".to_string(),
+            ));
+        }
+        b.slow_call_threshold_ms = slow_call_threshold_ms;
+        b.slow_call_rate_percent = slow_call_rate_percent;
+        Ok(())
+    }
+
+    /// Record many outcomes in one call, saving a boundary crossing per
+    /// outcome. Successes are applied before failures, each one as
+    /// `record_success` or `record_failure` would apply it, so the final state
+    /// (including any transitions in between) is the same as that sequence of
+    /// calls. Each outcome costs no more than it would across the boundary, so
+    /// batches of any size are accepted.
+    #[named(record_batch_named)]
+    #[wasm_bindgen]
+    pub fn record_batch(b, successes: u32, failures: u32, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.observe_time(current_time_ms);
+        for _ in 0..successes {
+            b.record_success();
+        }
+        for _ in 0..failures {
+            b.record_failure(current_time_ms);
+        }
+    }
+
+    /// Record a failure with an error category, e.g. `"timeout"` or `"5xx"`.
+    /// Only categories passed to `set_trip_categories` advance the failure
+    /// count; others are reported as `ignored_failures`.
+    #[named(record_failure_categorized_named)]
+    #[wasm_bindgen]
+    pub fn record_failure_categorized(b, category: &str, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.record_failure_categorized(category, current_time_ms);
+    }
+
+    /// Choose which failure categories are trip-worthy. An empty list, the
+    /// default, makes every category trip-worthy.
+    #[named(set_trip_categories_named)]
+    #[wasm_bindgen]
+    pub fn set_trip_categories(b, categories: Vec<String>) {
+        b.trip_categories = categories.into_iter().collect();
+    }
+
+    /// Get current breaker state as JSON string: the `get_status_obj` fields
+    /// plus `failures` and `successes`, which repeat `failure_count` and
+    /// `success_count` for older callers. `successes` counts successes
+    /// in the current window: the recent outcome window while Closed (the
+    /// last 100 calls, or the rate window in rate mode), the run of probe
+    /// successes while HalfOpen, and 0 while Open.
+    #[named(get_status_named)]
+    #[wasm_bindgen]
+    pub fn get_status(b) -> String {
+        b.status_json()
+    }
+
+    /// Drop failures that have aged out of the sliding window by
+    /// `current_time_ms`. Requests do this as they are checked; call it
+    /// before `get_status` on a breaker that sees no traffic so the reported
+    /// `failures` don't include expired ones.
+    #[named(compact_named)]
+    #[wasm_bindgen]
+    pub fn compact(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.compact(current_time_ms);
+    }
+
+    /// Get the breaker's static configuration (thresholds, timeouts, window,
+    /// backoff and mode settings) as a JSON string, without runtime counts
+    #[named(get_config_named)]
+    #[wasm_bindgen]
+    pub fn get_config(b) -> String {
+        serde_json::to_string(&b.config()).expect("config is always serializable")
+    }
+
+    /// Health score in [0.0, 1.0] for weighting traffic between instances.
+    ///
+    /// - Closed: share of successes among recent calls, 1.0 with no data
+    /// - HalfOpen: progress toward the successes needed to close
+    /// - Open: 0.0
+    #[named(health_score_named)]
+    #[wasm_bindgen]
+    pub fn health_score(b) -> f64 {
+        b.health_score()
+    }
+
+    /// Mean latency of the last 100 successful calls recorded with
+    /// `record_result`, or 0 with no data
+    #[named(latency_mean_named)]
+    #[wasm_bindgen]
+    pub fn latency_mean(b) -> f64 {
+        b.latency_mean()
+    }
+
+    /// Median latency of the last 100 successful calls, or 0 with no data
+    #[named(latency_p50_named)]
+    #[wasm_bindgen]
+    pub fn latency_p50(b) -> u64 {
+        b.latency_percentile(50)
+    }
+
+    /// 95th-percentile latency of the last 100 successful calls, or 0 with
+    /// no data
+    #[named(latency_p95_named)]
+    #[wasm_bindgen]
+    pub fn latency_p95(b) -> u64 {
+        b.latency_percentile(95)
+    }
+
+    /// Get the current breaker state without parsing the status JSON
+    #[named(current_state_named)]
+    #[wasm_bindgen]
+    pub fn current_state(b) -> BreakerState {
+        b.state
+    }
+
+    /// Get current breaker state as a JS object, converted directly rather
+    /// than parsed from JSON
+    #[named(get_status_obj_named)]
+    #[wasm_bindgen(unchecked_return_type = "BreakerStatus")]
+    pub fn get_status_obj(b) -> Result<JsValue, JsValue> {
+        to_js(&b.status())
+    }
+
+    /// Set the status fields of `get_status_obj` directly on `obj`. Reusing
+    /// one object lets a dashboard polling at high frequency avoid allocating
+    /// a new one on every poll.
+    #[named(get_status_into_named)]
+    #[wasm_bindgen]
+    pub fn get_status_into(
+        b,
+        #[wasm_bindgen(unchecked_param_type = "BreakerStatus")] obj: &js_sys::Object,
+    ) -> Result<(), JsValue> {
+        let status = to_js(&b.status())?;
+        js_sys::Object::assign(obj, &status.into());
+        Ok(())
+    }
+
+    /// Serialize the full breaker state, e.g. to survive a page reload
+    #[named(export_state_named)]
+    #[wasm_bindgen]
+    pub fn export_state(b) -> String {
+        b.to_json()
+    }
+
+    /// Restore state produced by `export_state`. Returns false and leaves the
+    /// current state untouched if the JSON is malformed, isn't a breaker
+    /// state, or holds a configuration the setters would reject.
+    #[named(import_state_named)]
+    #[wasm_bindgen]
+    pub fn import_state(b, json: &str) -> bool {
+        match CircuitBreakerState::from_json(json) {
+            Some(state) => {
+                *b = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Dump every field of the breaker, including internal ones such as
+    /// `half_open_calls`, as pretty-printed JSON for use as a test fixture
+    #[named(take_snapshot_named)]
+    #[wasm_bindgen]
+    pub fn take_snapshot(b) -> String {
+        serde_json::to_string_pretty(b).expect("breaker state is always serializable")
+    }
+
+    /// Set the breaker directly from a fixture, e.g. "Open, 4 failures, last
+    /// failure at T", without driving it there through calls. Omitted fields
+    /// take their defaults; unknown fields and invalid configuration are
+    /// rejected, leaving the current state untouched.
+    #[named(load_snapshot_named)]
+    #[wasm_bindgen]
+    pub fn load_snapshot(b, json: &str) -> Result<(), BreakerError> {
+        let state = CircuitBreakerState::from_snapshot(json)?;
+        *b = state;
+        Ok(())
+    }
+
+    /// Serialize the full breaker state in a compact versioned binary layout,
+    /// for frequent persistence (e.g. to IndexedDB) where JSON is too heavy
+    #[named(export_state_bytes_named)]
+    #[wasm_bindgen]
+    pub fn export_state_bytes(b) -> Vec<u8> {
+        b.to_bytes()
+    }
+
+    /// Restore state produced by `export_state_bytes`. Returns false and leaves
+    /// the current state untouched if the data is truncated, malformed, from a
+    /// different layout version, or holds a configuration the setters reject.
+    #[named(import_state_bytes_named)]
+    #[wasm_bindgen]
+    pub fn import_state_bytes(b, data: &[u8]) -> bool {
+        match CircuitBreakerState::from_bytes(data) {
+            Some(state) => {
+                *b = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Force the breaker open (kill switch). Unlike a trip caused by failures
+    /// it doesn't recover by itself: requests are denied as `open_forced`
+    /// until `reset_breaker`, `force_half_open` or `release_force_open`.
+    #[named(force_open_named)]
+    #[wasm_bindgen]
+    pub fn force_open(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.force_open(current_time_ms);
+    }
+
+    /// Let a breaker opened by `force_open` recover normally again, with the
+    /// recovery timeout counted from `current_time_ms`. Returns false if the
+    /// breaker wasn't forced.
+    #[named(release_force_open_named)]
+    #[wasm_bindgen]
+    pub fn release_force_open(b, current_time_ms: u64) -> bool {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.release_force_open(current_time_ms)
+    }
+
+    /// Move an Open breaker straight to HalfOpen so probing can start before
+    /// the recovery timeout elapses. Closed, HalfOpen and latched breakers are
+    /// unchanged.
+    #[named(force_half_open_named)]
+    #[wasm_bindgen]
+    pub fn force_half_open(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.force_half_open(current_time_ms);
+    }
+
+    /// Close the breaker immediately, skipping HalfOpen probing, once the
+    /// dependency is confirmed healthy out of band. The positive counterpart
+    /// to `force_open`: it also lifts a forced or latched Open. Unlike
+    /// `reset_breaker` only the failure and success counts are cleared;
+    /// settings, lifetime metrics, metadata and the backoff level are kept,
+    /// and no slow-start ramp or probation period follows.
+    #[named(force_close_named)]
+    #[wasm_bindgen]
+    pub fn force_close(b, current_time_ms: u64) {
+        let current_time_ms = host_time_ms(current_time_ms);
+        b.force_close(current_time_ms);
+    }
+
+    /// Reset the breaker to closed state.
+    ///
+    /// Clears all runtime state (counters, failure and latency windows,
+    /// backoff level, jitter, slow-start ramp, shedding credit) but keeps every
+    /// configured threshold and mode, as well as the lifetime metrics cleared by
+    /// `reset_metrics`.
+    #[named(reset_breaker_named)]
+    #[wasm_bindgen]
+    pub fn reset_breaker(b) {
+        b.reset();
+    }
+}
+
+/// Register a callback invoked as `cb(from, to, timestamp, name)` whenever
+/// any breaker changes state. Exceptions thrown by the callback are ignored.
+#[wasm_bindgen]
+pub fn set_on_transition(cb: &js_sys::Function) {
+    ON_TRANSITION.with(|slot| *slot.borrow_mut() = Some(cb.clone()));
+}
+
+per_breaker! {
+    /// Return and clear the recent transitions as a JSON array of
+    /// `{ from, to, timestamp }`, oldest first. A pull-based alternative to
+    /// `set_on_transition` that never calls back into JS.
+    #[named(drain_events_named)]
+    #[wasm_bindgen]
+    pub fn drain_events(b) -> String {
+        b.drain_events()
+    }
+}
+
+/// Drain every registered breaker's event log before the page or process
+/// shuts down, as a JSON array of `{ name, from, to, timestamp }` ordered
+/// by time. Each breaker is marked `quiesced` in its status so consumers
+/// can tell it was drained; transitions after the flush are still logged.
+/// Calling it again returns only what happened since, usually `[]`.
+#[wasm_bindgen]
+pub fn flush() -> String {
+    let mut events: Vec<FlushedEvent> = with_registry(|breakers| {
+        let mut events = Vec::new();
+        for (name, breaker) in breakers.iter_mut() {
+            breaker.quiesced = true;
+            events.extend(breaker.event_log.drain(..).map(|t| FlushedEvent {
+                name: name.clone(),
+                from: t.from.as_str(),
+                to: t.to.as_str(),
+                timestamp: t.at,
+            }));
+        }
+        events
+    });
+    events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.name.cmp(&b.name)));
+    serde_json::to_string(&events).expect("events are always serializable")
+}
+
+per_breaker! {
+    /// Keep up to `size` undrained transitions (32 by default), dropping the
+    /// oldest when full; 0 disables the log
+    #[named(set_event_log_size_named)]
+    #[wasm_bindgen]
+    pub fn set_event_log_size(b, size: u32) {
+        b.event_log_size = size;
+        b.trim_event_log();
+    }
+
+    /// Times of the most recent trips (Closed or HalfOpen to Open) as a JSON
+    /// array, newest first, e.g. to line trips up with deploys on a timeline
+    #[named(recent_trips_named)]
+    #[wasm_bindgen]
+    pub fn recent_trips(b) -> String {
+        let trips: Vec<u64> = b.trip_times.iter().rev().copied().collect();
+        serde_json::to_string(&trips).expect("trip times are always serializable")
+    }
+
+    /// Keep the times of up to `size` recent trips (16 by default), dropping
+    /// the oldest when full; 0 disables the history
+    #[named(set_trip_history_size_named)]
+    #[wasm_bindgen]
+    pub fn set_trip_history_size(b, size: u32) {
+        b.trip_history_size = size;
+        b.trim_trip_history();
+    }
+}
+
+/// Remove the transition callback
+#[wasm_bindgen]
+pub fn clear_on_transition() {
+    ON_TRANSITION.with(|slot| *slot.borrow_mut() = None);
+}
+
+per_breaker! {
+    /// Zero the lifetime counters (trips, rejections, outcome totals), which
+    /// `reset_breaker` keeps
+    #[named(reset_metrics_named)]
+    #[wasm_bindgen]
+    pub fn reset_metrics(b) {
+        b.reset_metrics();
+    }
+
+    /// Total time spent in each state as JSON `{ closed_ms, open_ms,
+    /// half_open_ms }`, including the current state up to `current_time_ms`.
+    /// Time is counted from the first timestamp the breaker sees, and again
+    /// from the next one after `reset_metrics`.
+    #[named(dwell_times_named)]
+    #[wasm_bindgen]
+    pub fn dwell_times(b, current_time_ms: u64) -> String {
+        let current_time_ms = host_time_ms(current_time_ms);
+        serde_json::to_string(&b.dwell_times(current_time_ms))
+            .expect("dwell times are always serializable")
+    }
+}
+
+/// Get every registered breaker's status object as a JSON object keyed by
+/// breaker name, sorted by name
+#[wasm_bindgen]
+pub fn get_all_status() -> String {
+    with_registry(|breakers| {
+        let all: BTreeMap<&str, Status> = breakers
+            .iter()
+            .map(|(name, breaker)| (name.as_str(), breaker.status()))
+            .collect();
+        serde_json::to_string(&all).expect("status is always serializable")
+    })
+}
+
+/// Serialize every registered breaker's full state as a JSON object keyed
+/// by breaker name, for `import_all`
+#[wasm_bindgen]
+pub fn export_all() -> String {
+    with_registry(|breakers| {
+        let all: BTreeMap<&str, &CircuitBreakerState> =
+            breakers.iter().map(|(name, breaker)| (name.as_str(), breaker)).collect();
+        serde_json::to_string(&all).expect("breaker state is always serializable")
+    })
+}
+
+/// Restore breakers from a JSON object mapping names to states produced by
+/// `export_state` or `export_all`, creating or replacing each one. Entries
+/// that aren't valid states, including ones with a configuration the
+/// setters would reject, are skipped. Returns how many were restored,
+/// or 0 if the document isn't a JSON object.
+#[wasm_bindgen]
+pub fn import_all(json: &str) -> u32 {
+    let Ok(entries) = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(json)
+    else {
+        return 0;
+    };
+    with_registry(|breakers| {
+        let mut restored = 0;
+        for (name, value) in entries {
+            if let Some(state) = CircuitBreakerState::from_value(value) {
+                breakers.insert(name, state);
+                restored += 1;
+            }
+        }
+        restored
+    })
+}
+
+/// Apply `f` to every registered breaker under a single registry borrow,
+/// then report the transitions it caused. Returns how many breakers there
+/// were.
+fn update_all_breakers(mut f: impl FnMut(&mut CircuitBreakerState)) -> u32 {
+    let transitions: Vec<(String, Vec<Transition>)> = with_registry(|breakers| {
+        breakers
+            .iter_mut()
+            .map(|(name, breaker)| {
+                f(breaker);
+                (name.clone(), std::mem::take(&mut breaker.pending_transitions))
+            })
+            .collect()
+    });
+    for (name, transitions) in &transitions {
+        notify_transitions(name, transitions);
+    }
+    transitions.len() as u32
+}
+
+/// Force every registered breaker open in one step, e.g. as an incident
+/// kill switch. Returns the number of breakers affected.
+#[wasm_bindgen]
+pub fn force_open_all(current_time_ms: u64) -> u32 {
+    let current_time_ms = host_time_ms(current_time_ms);
+    update_all_breakers(|b| b.force_open(current_time_ms))
+}
+
+/// Reset every registered breaker to Closed in one step, as
+/// `reset_breaker` does. Returns the number of breakers affected.
+#[wasm_bindgen]
+pub fn reset_all() -> u32 {
+    update_all_breakers(|b| b.reset())
+}
+
+/// Evaluate several named breakers at once, e.g. for a router choosing a
+/// healthy backend, as a JSON array of `{ name, allowed, state, error }`
+/// in the order given. Like `would_allow` it changes nothing and consumes
+/// no probe slots; a breaker due to probe reports `half_open`. Unknown
+/// names are reported with `allowed: false`, a null `state` and an
+/// `error` message.
+#[wasm_bindgen]
+pub fn evaluate_many(names: Vec<String>, current_time_ms: u64) -> String {
+    let current_time_ms = host_time_ms(current_time_ms);
+    let candidates: Vec<Candidate> = with_registry(|breakers| {
+        names
+            .into_iter()
+            .map(|name| match breakers.get(&name) {
+                Some(b) => {
+                    let state = if b.recovery_due(current_time_ms) {
+                        BreakerState::HalfOpen
+                    } else {
+                        b.state
+                    };
+                    Candidate {
+                        allowed: b.would_allow(current_time_ms),
+                        state: Some(state.as_str()),
+                        error: None,
+                        name,
+                    }
+                }
+                None => Candidate {
+                    allowed: false,
+                    state: None,
+                    error: Some(BreakerError::UnknownBreaker(name.clone()).to_string()),
+                    name,
+                },
+            })
+            .collect()
+    });
+    serde_json::to_string(&candidates).expect("candidates are always serializable")
+}
+
+/// Names of the breakers that are Open at `current_time_ms`, sorted. A
+/// breaker whose recovery timeout has elapsed counts as HalfOpen even if no
+/// request has moved it there yet.
+fn open_breaker_names(current_time_ms: u64) -> Vec<String> {
+    with_registry(|breakers| {
+        let mut names: Vec<String> = breakers
+            .iter()
+            .filter(|(_, breaker)| breaker.is_open_at(current_time_ms))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    })
+}
+
+/// False if any registered breaker is Open at `current_time_ms`, e.g. for a
+/// health-check endpoint. Does not change any breaker's state.
+#[wasm_bindgen]
+pub fn system_healthy(current_time_ms: u64) -> bool {
+    let current_time_ms = host_time_ms(current_time_ms);
+    open_breaker_names(current_time_ms).is_empty()
+}
+
+/// JSON array of the names of breakers Open at `current_time_ms`, sorted
+#[wasm_bindgen]
+pub fn open_breakers(current_time_ms: u64) -> String {
+    let current_time_ms = host_time_ms(current_time_ms);
+    serde_json::to_string(&open_breaker_names(current_time_ms))
+        .expect("names are always serializable")
+}
+
+/// Cargo features this build was compiled with
+const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "shared-state")]
+    "shared-state",
+];
+
+/// Feature introspection returned by `capabilities`
+#[derive(Serialize)]
+struct Capabilities {
+    version: &'static str,
+    modes: BTreeMap<&'static str, bool>,
+    features: &'static [&'static str],
+}
+
+/// Describe this build as JSON: the crate `version`, a `modes` object of
+/// flags for each optional mode, and the enabled cargo `features`, so JS
+/// can adapt to older binaries
+#[wasm_bindgen]
+pub fn capabilities() -> String {
+    let modes = BTreeMap::from([
+        ("rate", true),
+        ("adaptive", true),
+        ("count_window", true),
+        ("sliding_window", true),
+        ("backoff", true),
+        ("gated_recovery", true),
+        ("antiflap", true),
+        ("jitter", true),
+        ("slow_call", true),
+        ("slow_start", true),
+        ("weighted_failures", true),
+        ("quorum", true),
+        ("half_open_traffic_ramp", true),
+        ("shadow", true),
+        ("named_breakers", true),
+        ("event_log", true),
+        ("snapshots", true),
+        ("date_now", cfg!(target_arch = "wasm32")),
+    ]);
+    let capabilities = Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        modes,
+        features: ENABLED_FEATURES,
+    };
+    serde_json::to_string(&capabilities).expect("capabilities are always serializable")
+}
+
+/// Escape a label value per the Prometheus text exposition format
+fn prometheus_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Export every registered breaker in Prometheus text format, labelled by
+/// breaker name. The state gauge is 0=closed, 1=half_open, 2=open.
+#[wasm_bindgen]
+pub fn metrics_prometheus() -> String {
+    type Metric = fn(&CircuitBreakerState) -> u64;
+    let metrics: [(&str, &str, &str, Metric); 4] = [
+        (
+            "circuit_breaker_state",
+            "gauge",
+            "Current state (0=closed, 1=half_open, 2=open)",
+            |b| b.state.to_code() as u64,
+        ),
+        (
+            "circuit_breaker_failures_total",
+            "counter",
+            "Failures recorded",
+            |b| b.total_failures,
+        ),
+        (
+            "circuit_breaker_successes_total",
+            "counter",
+            "Successes recorded",
+            |b| b.total_successes,
+        ),
+        (
+            "circuit_breaker_trips_total",
+            "counter",
+            "Transitions into the open state",
+            |b| b.trip_count,
+        ),
+    ];
+
+    with_registry(|breakers| {
+        let mut names: Vec<&String> = breakers.keys().collect();
+        names.sort();
+
+        let mut out = String::new();
+        for (metric, kind, help, value) in metrics {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", metric, help, metric, kind));
+            for name in &names {
+                out.push_str(&format!(
+                    "{}{{breaker=\"{}\"}} {}\n",
+                    metric,
+                    prometheus_label(name),
+                    value(&breakers[*name])
+                ));
+            }
+        }
+        out
+    })
+}
+
+/// Make room in the registry for `n` breakers in total, so creating that
+/// many at startup doesn't rehash along the way. Does nothing if the
+/// registry can already hold them.
+#[wasm_bindgen]
+pub fn reserve_breakers(n: usize) {
+    with_registry(|breakers| breakers.reserve(n.saturating_sub(breakers.len())));
+}
+
+/// How many breakers the registry can hold before it next has to grow
+#[wasm_bindgen]
+pub fn breaker_capacity() -> usize {
+    with_registry(|breakers| breakers.capacity())
+}
+
+/// Create (or replace) a named circuit breaker; `recovery_timeout` is in seconds
+#[wasm_bindgen]
+pub fn create_breaker(
+    name: &str,
+    failure_threshold: u32,
+    recovery_timeout: u64,
+) -> Result<(), BreakerError> {
+    check_failure_threshold(failure_threshold)?;
+    let breaker = CircuitBreakerState::new(failure_threshold, secs_to_ms(recovery_timeout));
+    with_registry(|breakers| breakers.insert(name.to_string(), breaker));
+    Ok(())
+}
+
+per_breaker! {
+    /// Attach a metadata entry (e.g. service, region, owner) to the breaker.
+    /// It is reported under `metadata` in status and travels with exports.
+    /// Setting an existing key replaces its value; a new key is rejected once
+    /// the breaker has 32.
+    #[named(set_metadata_named)]
+    #[wasm_bindgen]
+    pub fn set_metadata(b, key: &str, value: &str) -> Result<(), BreakerError> {
+        b.set_metadata(key, value)
+    }
+}
+
+/// Zero-argument variants that read the time from `Date.now()`. They are
+/// only built for wasm32, where a JS host provides the clock; elsewhere use
+/// the explicit-timestamp functions. The clock is read in milliseconds
+/// whatever `set_time_unit` says. Don't mix the two on one breaker
+/// unless the caller's timestamps come from `Date.now()` too, or the
+/// recovery timer will be measured against inconsistent clocks.
+#[cfg(target_arch = "wasm32")]
+mod now {
+    use super::*;
+
+    /// The current time in milliseconds, bypassing the host time unit
+    fn now_ms() -> u64 {
+        js_sys::Date::now() as u64
+    }
+
+    #[wasm_bindgen]
+    pub fn allow_request_now() -> bool {
+        with_default_breaker(|b| b.allow_request(now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn would_allow_now() -> bool {
+        with_default_breaker(|b| b.would_allow(now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn time_until_retry_now() -> Option<u64> {
+        with_default_breaker(|b| b.time_until_retry(now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn guard_begin_now() -> u32 {
+        with_default_breaker(|b| b.guard_begin(now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn guard_end_now(token: u32, success: bool) -> bool {
+        with_default_breaker(|b| b.guard_end(token, success, now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn record_failure_now() {
+        with_default_breaker(|b| b.record_failure(now_ms()));
+    }
+
+    #[wasm_bindgen]
+    pub fn record_result_now(success: bool, latency_ms: u64) {
+        with_default_breaker(|b| b.record_result(success, latency_ms, now_ms()));
+    }
+
+    #[wasm_bindgen]
+    pub fn record_failure_categorized_now(category: &str) {
+        with_default_breaker(|b| b.record_failure_categorized(category, now_ms()));
+    }
+
+    #[wasm_bindgen]
+    pub fn allow_request_named_now(name: &str) -> Result<bool, BreakerError> {
+        with_breaker(name, |b| b.allow_request(now_ms()))
+    }
+
+    #[wasm_bindgen]
+    pub fn record_failure_named_now(name: &str) -> Result<(), BreakerError> {
+        with_breaker(name, |b| b.record_failure(now_ms()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Under `shared-state` all tests share one registry, so each holds
+    /// this lock while it runs and starts from a fresh registry
+    fn isolated_registry() -> Option<std::sync::MutexGuard<'static, ()>> {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        if !cfg!(feature = "shared-state") {
+            return None;
+        }
+        let guard = LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        with_registry(|breakers| *breakers = default_registry());
+        Some(guard)
+    }
+
+    #[test]
+    fn test_breaker_starts_closed() {
+        let _registry = isolated_registry();
+        reset_breaker();
+        assert!(allow_request(0));
+    }
+
+    #[test]
+    fn test_breaker_opens_after_failures() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        reset_breaker();
+        
+        record_failure(1000);
+        record_failure(2000);
+        assert!(allow_request(3000)); // Still closed after 2 failures
+        
+        record_failure(3000);
+        assert!(!allow_request(4000)); // Now open after 3 failures
+    }
+
+    #[test]
+    fn test_half_open_max_configurable() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 1).unwrap();
+        record_failure(0);
+        assert!(allow_request(1000)); // HalfOpen, single probe
+        assert!(!allow_request(1000));
+
+        record_success(); // One success closes with half_open_max == 1
+        assert!(get_status().contains(r#""state":"closed""#));
+    }
+
+    #[test]
+    fn test_half_open_max_rejects_zero() {
+        let _registry = isolated_registry();
+        assert!(matches!(
+            init_breaker_full(3, 60, 0),
+            Err(BreakerError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_reset_preserves_half_open_max() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 5).unwrap();
+        reset_breaker();
+        record_failure(0);
+        for _ in 0..5 {
+            assert!(allow_request(1000));
+        }
+        assert!(!allow_request(1000));
+    }
+
+    #[test]
+    fn test_window_drops_old_failures() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 60, 10_000).unwrap();
+        record_failure(0);
+        record_failure(5_000);
+        record_failure(20_000); // First two have aged out
+        assert!(allow_request(20_000));
+
+        record_failure(21_000);
+        record_failure(22_000);
+        assert!(!allow_request(22_000));
+    }
+
+    #[test]
+    fn test_window_buffer_is_bounded() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 60, 1_000_000).unwrap();
+        with_default_breaker(|b| {
+            for t in 0..100 {
+                b.record_windowed_failure(1000, t);
+            }
+            assert_eq!(b.failure_times.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_named_breakers_are_independent() {
+        let _registry = isolated_registry();
+        create_breaker("payments", 2, 60).unwrap();
+        create_breaker("search", 2, 60).unwrap();
+
+        record_failure_named("payments", 1000).unwrap();
+        record_failure_named("payments", 2000).unwrap();
+
+        assert!(!allow_request_named("payments", 3000).unwrap());
+        assert!(allow_request_named("search", 3000).unwrap());
+        assert!(allow_request(3000)); // Default breaker untouched
+    }
+
+    #[test]
+    fn test_unknown_named_breaker_errors() {
+        let _registry = isolated_registry();
+        assert_eq!(
+            allow_request_named("missing", 0),
+            Err(BreakerError::UnknownBreaker("missing".to_string()))
+        );
+        assert!(record_success_named("missing").is_err());
+        assert!(record_failure_named("missing", 0).is_err());
+        assert!(get_status_named("missing").is_err());
+    }
+
+    #[test]
+    fn test_rate_mode_waits_for_minimum_calls() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 4, 60).unwrap();
+        record_failure(0);
+        record_failure(0);
+        record_failure(0);
+        assert!(allow_request(0)); // 3 of 4 required calls, 100% failures
+
+        record_failure(0);
+        assert!(!allow_request(0));
+    }
+
+    #[test]
+    fn test_rate_mode_trips_on_percentage() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 4, 60).unwrap();
+        record_success();
+        record_success();
+        record_success();
+        record_failure(0); // 25% failures
+        assert!(allow_request(0));
+
+        record_failure(0); // Window is now S S F F, 50%
+        assert!(!allow_request(0));
+    }
+
+    #[test]
+    fn test_rate_mode_rejects_bad_config() {
+        let _registry = isolated_registry();
+        assert!(init_breaker_rate(0, 10, 60).is_err());
+        assert!(init_breaker_rate(101, 10, 60).is_err());
+        assert!(init_breaker_rate(50, 0, 60).is_err());
+    }
+
+    #[test]
+    fn test_structured_status_fields() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        record_failure(1234);
+        record_success();
+
+        let status = with_default_breaker(|b| serde_json::to_value(b.status()).unwrap());
+        assert_eq!(
+            status,
+            serde_json::json!({
+                "state": "closed",
+                "failure_count": 1,
+                "success_count": 1,
+                "failure_threshold": 3,
+                "recovery_timeout": 60.0,
+                "recovery_timeout_ms": 60_000,
+                "last_failure_time": 1234,
+                "trip_count": 0,
+                "rejected_count": 0,
+                "state_changed_at": null,
+                "last_transition_cause": null,
+                "ignored_failures": 0,
+                "shadow_mode": false,
+                "short_circuited_count": 0,
+                "evaluations_total": 0,
+                "latched": false,
+                "forced_open": false,
+                "failure_score": 1.0,
+                "enabled": true,
+                "half_open_calls": 0,
+                "half_open_max": 3,
+                "timeout_count": 0,
+                "error_count": 1,
+                "metadata": {},
+                "quiesced": false,
+                "window_truncated": false,
+            })
+        );
+
+        let mut json: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        assert_eq!(fields.remove("failures"), Some(1.into()));
+        assert_eq!(fields.remove("successes"), Some(1.into()));
+        assert_eq!(json, status);
+    }
+
+    #[test]
+    fn test_decreasing_timestamps_do_not_strand_breaker() {
+        let _registry = isolated_registry();
+        init_breaker(2, 1);
+        record_failure(10_000);
+        record_failure(10_000);
+        assert!(!allow_request(5_000)); // Clock went backwards, no panic
+
+        record_failure(3_000); // Stale failure is ignored
+        assert!(allow_request(11_000)); // Recovers relative to the real trip
+    }
+
+    #[test]
+    fn test_backoff_doubles_recovery_timeout() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 10, 35).unwrap();
+        record_failure(0);
+        assert!(allow_request(10_000)); // Base timeout
+
+        record_failure(10_000); // Probe failed, wait doubles to 20s
+        assert!(!allow_request(29_000));
+        assert!(allow_request(30_000));
+
+        record_failure(30_000); // 40s capped at 35s
+        assert!(!allow_request(64_000));
+        assert!(allow_request(65_000));
+    }
+
+    #[test]
+    fn test_backoff_resets_after_close() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 10, 100).unwrap();
+        record_failure(0);
+        assert!(allow_request(10_000));
+        record_failure(10_000);
+        assert!(allow_request(30_000));
+        for _ in 0..3 {
+            record_success();
+        }
+
+        record_failure(40_000); // Fresh trip waits the base timeout
+        assert!(allow_request(50_000));
+    }
+
+    #[test]
+    fn test_jitter_spreads_recovery() {
+        let _registry = isolated_registry();
+        for name in ["tab-a", "tab-b"] {
+            create_breaker(name, 1, 10).unwrap();
+        }
+        set_recovery_jitter_named("tab-a", 50, 1).unwrap();
+        set_recovery_jitter_named("tab-b", 50, 2).unwrap();
+        record_failure_named("tab-a", 0).unwrap();
+        record_failure_named("tab-b", 0).unwrap();
+
+        let first_probe = |name: &str| {
+            (10_000..=15_000)
+                .step_by(10)
+                .find(|&t| allow_request_named(name, t).unwrap())
+                .unwrap()
+        };
+        assert_ne!(first_probe("tab-a"), first_probe("tab-b"));
+    }
+
+    #[test]
+    fn test_jitter_applies_to_short_timeouts() {
+        let _registry = isolated_registry();
+        let jitters: Vec<u64> = (1..=8)
+            .map(|seed| {
+                init_breaker_ms(1, 50).unwrap();
+                set_recovery_jitter(50, seed).unwrap();
+                record_failure(seed * 1_000);
+                with_default_breaker(|b| b.jitter_ms)
+            })
+            .collect();
+        assert!(jitters.iter().all(|&j| j <= 25));
+        assert!(jitters.iter().any(|&j| j > 0));
+    }
+
+    #[test]
+    fn test_jitter_rejects_large_fraction() {
+        let _registry = isolated_registry();
+        assert!(set_recovery_jitter(51, 1).is_err());
+        assert!(set_recovery_jitter_named("missing", 10, 1).is_err());
+    }
+
+    #[test]
+    fn test_transitions_queued_only_on_change() {
+        let _registry = isolated_registry();
+        with_registry(|breakers| {
+            let breaker = breakers.get_mut(DEFAULT_BREAKER).unwrap();
+            breaker.configure(2, 1);
+            breaker.allow_request(0);
+            breaker.record_failure(100);
+            assert!(breaker.pending_transitions.is_empty());
+
+            breaker.record_failure(200);
+            breaker.record_failure(300); // Already Open, no new event
+            breaker.allow_request(1_300);
+            let seen: Vec<_> = breaker
+                .pending_transitions
+                .iter()
+                .map(|t| (t.from.as_str(), t.to.as_str(), t.at))
+                .collect();
+            assert_eq!(seen, [("closed", "open", 200), ("open", "half_open", 1_300)]);
+        });
+    }
+
+    #[test]
+    fn test_transitions_drained_after_each_call() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure(0);
+        with_default_breaker(|b| assert!(b.pending_transitions.is_empty()));
+    }
+
+    #[test]
+    fn test_would_allow_matches_allow_request() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        assert!(would_allow(0));
+        record_failure(0);
+        assert!(!would_allow(500));
+        assert!(would_allow(1_000)); // Recovery due, but not committed
+        assert!(get_status().contains(r#""state":"open""#));
+    }
+
+    #[test]
+    fn test_would_allow_never_consumes_probes() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 2).unwrap();
+        record_failure(0);
+        assert!(allow_request(1_000)); // Enter HalfOpen, one probe used
+        for _ in 0..10 {
+            assert!(would_allow(1_000));
+        }
+        assert!(allow_request(1_000));
+        assert!(!would_allow(1_000));
+        assert!(!allow_request(1_000));
+    }
+
+    #[test]
+    fn test_time_until_retry_counts_down() {
+        let _registry = isolated_registry();
+        init_breaker(1, 10);
+        assert_eq!(time_until_retry(0), None);
+
+        record_failure(1_000);
+        assert_eq!(time_until_retry(3_000), Some(8_000));
+        assert_eq!(time_until_retry(50_000), Some(0)); // Saturates, never negative
+
+        assert!(allow_request(11_000));
+        assert_eq!(time_until_retry(11_000), None); // HalfOpen
+    }
+
+    #[test]
+    fn test_time_until_retry_reflects_backoff() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 10, 100).unwrap();
+        record_failure(0);
+        assert!(allow_request(10_000));
+        record_failure(10_000);
+        assert_eq!(time_until_retry(10_000), Some(20_000));
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let _registry = isolated_registry();
+        init_breaker_full(2, 30, 4).unwrap();
+        record_failure(1_000);
+        record_failure(2_000);
+        let exported = export_state();
+
+        reset_breaker();
+        assert!(import_state(&exported));
+        assert_eq!(export_state(), exported);
+        assert!(!allow_request(2_500)); // Still tripped after "reload"
+        with_default_breaker(|b| {
+            assert_eq!(b.failure_threshold, 2);
+            assert_eq!(b.recovery_timeout_ms, 30_000);
+            assert_eq!(b.half_open_max_calls, 4);
+            assert_eq!(b.half_open_success_threshold, 4);
+            assert_eq!(b.last_failure_time, Some(2_000));
+        });
+    }
+
+    #[test]
+    fn test_import_rejects_malformed_json() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure(0);
+        let before = export_state();
+
+        assert!(!import_state("{not json"));
+        assert!(!import_state(r#"{"state":"Sideways"}"#));
+        assert_eq!(export_state(), before);
+    }
+
+    #[test]
+    fn test_slow_calls_trip_breaker() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 4, 60).unwrap();
+        set_slow_call_threshold(500, 50).unwrap();
+        record_result(true, 100, 0);
+        record_result(true, 900, 10);
+        record_result(true, 100, 20);
+        assert!(allow_request(20)); // 3 of 4 required calls
+
+        record_result(true, 700, 30); // 2 of 4 slow
+        assert!(!allow_request(30));
+        assert_eq!(time_until_retry(30), Some(60_000));
+    }
+
+    #[test]
+    fn test_slow_failure_not_double_counted() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 4, 60).unwrap();
+        set_slow_call_threshold(500, 50).unwrap();
+        record_result(true, 100, 0);
+        record_result(true, 100, 0);
+        record_result(true, 900, 0); // Slow success
+        record_result(false, 900, 0); // Slow failure counts only as a failure
+        assert!(allow_request(0)); // 25% slow, 25% failed: neither trips
+    }
+
+    #[test]
+    fn test_minimum_calls_gates_tripping() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_minimum_calls(5);
+        for t in 0..4 {
+            record_failure(t);
+            assert!(allow_request(t)); // Below minimum, still Closed
+        }
+        assert!(get_status().contains(r#""failures":4"#));
+
+        record_failure(4); // Fifth call reaches the minimum
+        assert!(!allow_request(4));
+    }
+
+    #[test]
+    fn test_minimum_calls_counts_successes() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        set_minimum_calls(3);
+        record_success();
+        record_success();
+        record_failure(0);
+        assert!(!allow_request(0));
+    }
+
+    #[test]
+    fn test_counters_saturate_instead_of_wrapping() {
+        let _registry = isolated_registry();
+        init_breaker(u32::MAX, 60);
+        with_default_breaker(|b| {
+            b.total_successes = u64::MAX - 1;
+            b.failure_count = u32::MAX - 2;
+        });
+        for _ in 0..3 {
+            record_success();
+        }
+        record_failure(0);
+        record_failure(0);
+        with_default_breaker(|b| {
+            assert_eq!(b.total_successes, u64::MAX);
+            assert_eq!(b.failure_count, u32::MAX);
+        });
+    }
+
+    #[test]
+    fn test_recovery_timeout_in_milliseconds() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_500).unwrap();
+        record_failure(0);
+        assert!(!allow_request(1_499)); // No rounding down to whole seconds
+        assert!(allow_request(1_500));
+    }
+
+    #[test]
+    fn test_force_half_open_permits_probe() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure(0);
+        assert!(!allow_request(1_000));
+
+        force_half_open(1_000);
+        assert!(get_status().contains(r#""state":"half_open""#));
+        assert!(allow_request(1_000));
+    }
+
+    #[test]
+    fn test_force_half_open_ignores_closed_breaker() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        force_half_open(0);
+        assert!(get_status().contains(r#""state":"closed""#));
+    }
+
+    #[test]
+    fn test_guarded_call_records_outcome() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        let token = guard_begin(0);
+        assert_ne!(token, GUARD_DENIED);
+        assert_eq!(outstanding_guards(), 1);
+
+        assert!(guard_end(token, false, 10));
+        assert_eq!(outstanding_guards(), 0);
+        assert_eq!(guard_begin(20), GUARD_DENIED);
+        assert!(!guard_end(token, false, 30)); // Already ended
+    }
+
+    #[test]
+    fn test_stale_guard_token_ignored() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 1).unwrap();
+        let stale = guard_begin(0);
+        record_failure(0); // Trips, starting a new generation
+        assert!(!guard_end(stale, true, 10));
+
+        let probe = guard_begin(1_000);
+        assert_ne!(probe, GUARD_DENIED);
+        assert_ne!(probe, stale);
+        assert!(guard_end(probe, true, 1_010));
+        assert!(get_status().contains(r#""state":"closed""#));
+    }
+
+    #[test]
+    fn test_current_state_enum() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(0);
+        assert_eq!(current_state(), BreakerState::Open);
+        allow_request(1_000);
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+    }
+
+    #[test]
+    fn test_lifetime_trip_and_reject_counts() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        record_failure(0);
+        assert!(!allow_request(100));
+        assert!(!allow_request(200));
+        assert!(allow_request(1_000)); // HalfOpen probe
+        record_failure(1_000); // Second trip
+
+        reset_breaker(); // Keeps lifetime metrics
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["trip_count"], 2);
+        assert_eq!(status["rejected_count"], 2);
+
+        reset_metrics();
+        assert!(get_status().contains(r#""trip_count":0,"rejected_count":0"#));
+    }
+
+    #[test]
+    fn test_half_open_single_probe_many_successes() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_half_open_limits(1, 3).unwrap();
+        record_failure(0);
+
+        for _ in 0..2 {
+            assert!(allow_request(1_000));
+            assert!(!allow_request(1_000)); // One probe at a time
+            record_success();
+            assert_eq!(current_state(), BreakerState::HalfOpen);
+        }
+        assert!(allow_request(1_000));
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_many_probes_single_success() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_half_open_limits(5, 1).unwrap();
+        record_failure(0);
+        for _ in 0..5 {
+            assert!(allow_request(1_000));
+        }
+        assert!(!allow_request(1_000));
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert!(set_half_open_limits(0, 1).is_err());
+    }
+
+    #[test]
+    fn test_failed_half_open_requires_full_threshold_again() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_half_open_limits(3, 2).unwrap();
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_success(); // One of two
+        assert!(allow_request(1_000));
+        record_failure(1_000); // Re-open
+        with_default_breaker(|b| {
+            assert_eq!(b.success_count, 0);
+            assert_eq!(b.half_open_calls, 0);
+        });
+
+        assert!(allow_request(2_000));
+        record_success();
+        assert_eq!(current_state(), BreakerState::HalfOpen); // Not closed by a stale success
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_health_score_tracks_recent_outcomes() {
+        let _registry = isolated_registry();
+        init_breaker(10, 1);
+        assert_eq!(health_score(), 1.0);
+        record_success();
+        record_success();
+        record_success();
+        record_failure(0);
+        assert_eq!(health_score(), 0.75);
+    }
+
+    #[test]
+    fn test_health_score_by_state() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 2).unwrap();
+        record_failure(0);
+        assert_eq!(health_score(), 0.0);
+
+        allow_request(1_000);
+        record_success();
+        assert_eq!(health_score(), 0.5);
+        record_success();
+        assert_eq!(health_score(), 1.0); // Freshly closed
+    }
+
+    #[test]
+    fn test_prometheus_exposition() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_success();
+        record_failure(0);
+        let text = metrics_prometheus();
+
+        for metric in [
+            "circuit_breaker_state",
+            "circuit_breaker_failures_total",
+            "circuit_breaker_successes_total",
+            "circuit_breaker_trips_total",
+        ] {
+            assert!(text.contains(&format!("# HELP {} ", metric)));
+        }
+        assert!(text.contains("# TYPE circuit_breaker_state gauge\n"));
+        assert!(text.contains("# TYPE circuit_breaker_trips_total counter\n"));
+        assert!(text.contains("circuit_breaker_state{breaker=\"default\"} 2\n"));
+        assert!(text.contains("circuit_breaker_failures_total{breaker=\"default\"} 1\n"));
+        assert!(text.contains("circuit_breaker_successes_total{breaker=\"default\"} 1\n"));
+        assert!(text.contains("circuit_breaker_trips_total{breaker=\"default\"} 1\n"));
+    }
+
+    #[test]
+    fn test_prometheus_escapes_labels() {
+        let _registry = isolated_registry();
+        create_breaker("a\"b\\c", 1, 60).unwrap();
+        assert!(metrics_prometheus().contains(r#"circuit_breaker_state{breaker="a\"b\\c"} 0"#));
+    }
+
+    #[test]
+    fn test_open_without_failure_time_recovers() {
+        let _registry = isolated_registry();
+        let open = r#"{"state": "Open", "recovery_timeout_ms": 1000}"#;
+        assert!(import_state(open));
+        assert_eq!(with_default_breaker(|b| b.last_failure_time), None);
+        assert!(!allow_request(5_000)); // Starts the recovery timer
+        assert_eq!(time_until_retry(5_500), Some(500));
+        assert!(allow_request(6_000));
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+
+        load_snapshot(open).unwrap();
+        assert!(!allow_request(7_000));
+        assert!(allow_request(8_000));
+    }
+
+    #[test]
+    fn test_batch_matches_individual_calls() {
+        let _registry = isolated_registry();
+        let scenario = |batch: bool| {
+            init_breaker_full(3, 1, 2).unwrap();
+            reset_breaker();
+            reset_metrics();
+            record_failure(0);
+            record_failure(0);
+            if batch {
+                record_batch(2, 2, 500);
+            } else {
+                record_success();
+                record_success();
+                record_failure(500);
+                record_failure(500);
+            }
+            export_state()
+        };
+        assert_eq!(scenario(true), scenario(false));
+        assert_eq!(current_state(), BreakerState::Open);
+
+        init_breaker(3, 60);
+        reset_metrics();
+        record_batch(50_000, 2, 600);
+        assert_eq!(current_state(), BreakerState::Closed);
+        with_default_breaker(|b| assert_eq!(b.total_successes, 50_000));
+    }
+
+    #[test]
+    fn test_state_changed_at_tracks_transitions() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        reset_breaker();
+        record_failure(2_000);
+        assert!(get_status().contains(r#""state_changed_at":2000"#));
+
+        allow_request(3_500);
+        assert!(get_status().contains(r#""state_changed_at":3500"#));
+        allow_request(3_600); // No transition, timestamp unchanged
+        assert!(get_status().contains(r#""state_changed_at":3500"#));
+
+        force_open(4_000);
+        assert!(get_status().contains(r#""state_changed_at":4000"#));
+        reset_breaker();
+        assert!(get_status().contains(r#""state_changed_at":4000"#));
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_ignored_category_does_not_trip() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_trip_categories(vec!["5xx".to_string(), "timeout".to_string()]);
+        for t in 0..5 {
+            record_failure_categorized("4xx", t);
+        }
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert!(get_status().contains(r#""failures":0"#));
+        assert!(get_status().contains(r#""ignored_failures":5"#));
+
+        record_failure_categorized("5xx", 10);
+        record_failure_categorized("timeout", 20);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_ignored_category_keeps_half_open_success_run() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_trip_categories(vec!["5xx".to_string()]);
+        record_failure(0);
+        for _ in 0..3 {
+            assert!(allow_request(1_000));
+        }
+        record_success();
+        record_failure_categorized("404", 1_001);
+        assert!(allow_request(1_002));
+        record_success();
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_all_categories_trip_by_default() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure_categorized("anything", 0);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_all_status_sorted_by_name() {
+        let _registry = isolated_registry();
+        create_breaker("zeta", 1, 60).unwrap();
+        create_breaker("alpha", 1, 60).unwrap();
+        record_failure_named("zeta", 0).unwrap();
+
+        let all = get_all_status();
+        let alpha = all.find(r#""alpha":"#).unwrap();
+        let default = all.find(r#""default":"#).unwrap();
+        let zeta = all.find(r#""zeta":"#).unwrap();
+        assert!(alpha < default && default < zeta);
+
+        let parsed: serde_json::Value = serde_json::from_str(&all).unwrap();
+        assert_eq!(parsed["zeta"]["state"], "open");
+        assert_eq!(parsed["alpha"]["state"], "closed");
+    }
+
+    #[test]
+    fn test_all_status_empty_registry() {
+        let _registry = isolated_registry();
+        with_registry(|breakers| breakers.clear());
+        assert_eq!(get_all_status(), "{}");
+
+        // The default breaker comes back on next use
+        assert!(allow_request(0));
+        assert!(get_all_status().contains(DEFAULT_BREAKER));
+    }
+
+    fn recover_with_slow_start(ramp_ms: u64) {
+        init_breaker_full(1, 1, 1).unwrap();
+        set_slow_start(ramp_ms);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_success(); // Closed at t=1000, ramp begins
+    }
+
+    #[test]
+    fn test_slow_start_admits_ten_percent_at_start() {
+        let _registry = isolated_registry();
+        recover_with_slow_start(10_000);
+        let admitted = (0..100).filter(|_| allow_request(1_000)).count();
+        assert_eq!(admitted, 10);
+    }
+
+    #[test]
+    fn test_slow_start_full_traffic_after_ramp() {
+        let _registry = isolated_registry();
+        recover_with_slow_start(10_000);
+        let midway = (0..100).filter(|_| allow_request(6_000)).count();
+        assert_eq!(midway, 55); // 100 + 900 * 5000 / 10000 = 550 permille
+        assert!((0..100).all(|_| allow_request(11_000)));
+        with_default_breaker(|b| assert!(b.ramp_started_at.is_none()));
+    }
+
+    #[test]
+    fn test_no_slow_start_after_plain_reset() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_slow_start(10_000);
+        reset_breaker();
+        assert!((0..10).all(|_| allow_request(0)));
+    }
+
+    #[test]
+    fn test_configure_breaker_preserves_state() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure(0);
+        configure_breaker(10, 1).unwrap();
+        assert_eq!(current_state(), BreakerState::Open);
+        assert!(get_status().contains(r#""failures":1"#));
+        assert!(allow_request(1_000)); // New timeout applies to the live trip
+    }
+
+    #[test]
+    fn test_decay_keeps_alternating_outcomes_closed() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_decay_on_success(true);
+        for t in 0..1_000 {
+            record_failure(t);
+            record_success();
+        }
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert!(get_status().contains(r#""failures":0"#));
+
+        record_failure(2_000);
+        record_failure(2_001); // A genuine burst still trips
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_without_decay_sporadic_failures_trip() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        record_failure(0);
+        record_success();
+        record_failure(1);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_current_recovery_timeout_follows_backoff() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 10, 100).unwrap();
+        assert_eq!(current_recovery_timeout_ms(), 10_000);
+        record_failure(0);
+        assert_eq!(current_recovery_timeout_ms(), 10_000);
+        assert!(allow_request(10_000));
+        assert_eq!(current_recovery_timeout_ms(), 20_000); // If this probe fails
+        record_failure(10_000);
+        assert_eq!(current_recovery_timeout_ms(), 20_000);
+    }
+
+    #[test]
+    fn test_current_recovery_timeout_includes_jitter() {
+        let _registry = isolated_registry();
+        init_breaker(1, 10);
+        set_recovery_jitter(50, 7).unwrap();
+        record_failure(0);
+        let timeout = current_recovery_timeout_ms();
+        assert!((10_000..=15_000).contains(&timeout));
+        assert_eq!(time_until_retry(0), Some(timeout));
+    }
+
+    #[test]
+    fn test_shadow_mode_never_denies() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_shadow_mode(true);
+        record_failure(0);
+        record_failure(0);
+        assert!(allow_request(100));
+        assert!(allow_request(200));
+
+        let status = get_status();
+        assert!(status.contains(r#""state":"open""#));
+        assert!(status.contains(r#""rejected_count":2"#)); // Would have been denied
+        assert!(status.contains(r#""shadow_mode":true"#));
+
+        set_shadow_mode(false);
+        assert!(!allow_request(300));
+    }
+
+    #[test]
+    fn test_reset_clears_runtime_keeps_config() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(2, 10, 100).unwrap();
+        set_half_open_limits(2, 4).unwrap();
+        record_failure(0);
+        record_failure(0);
+        assert!(allow_request(10_000));
+        record_failure(10_000); // Backoff level 1
+
+        reset_breaker();
+        with_default_breaker(|b| {
+            assert_eq!(b.consecutive_trips, 0);
+            assert_eq!(b.failure_count, 0);
+            assert!(b.last_failure_time.is_none());
+            assert_eq!(b.failure_threshold, 2);
+            assert_eq!(b.recovery_timeout_ms, 10_000);
+            assert_eq!(b.max_recovery_timeout_ms, 100_000);
+            assert_eq!(b.half_open_max_calls, 2);
+            assert_eq!(b.half_open_success_threshold, 4);
+        });
+
+        record_failure(20_000);
+        record_failure(20_000);
+        assert!(allow_request(30_000)); // Base timeout again, not backed off
+    }
+
+    #[test]
+    fn test_short_circuited_count() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 60, 1).unwrap();
+        record_failure(0);
+        for t in 0..10 {
+            assert!(!allow_request(t));
+        }
+        assert!(get_status().contains(r#""short_circuited_count":10"#));
+        assert!(get_status().contains(r#""failures":1"#)); // Distinct from failures
+
+        force_half_open(100);
+        assert!(allow_request(100));
+        assert!(!allow_request(100)); // Probe budget exhausted also counts
+        assert!(get_status().contains(r#""short_circuited_count":11"#));
+    }
+
+    #[test]
+    fn test_builder_installs_config() {
+        let _registry = isolated_registry();
+        BreakerBuilder::new()
+            .with_failure_threshold(2)
+            .with_recovery_timeout_ms(1_500)
+            .with_half_open_max(1)
+            .with_window_ms(10_000)
+            .build()
+            .unwrap();
+        record_failure(0);
+        record_failure(20_000); // First failure aged out of the window
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(20_001);
+        assert_eq!(current_state(), BreakerState::Open);
+        assert!(allow_request(21_501));
+        assert!(!allow_request(21_501)); // Single probe
+    }
+
+    #[test]
+    fn test_builder_named_and_validation() {
+        let _registry = isolated_registry();
+        BreakerBuilder::new().with_failure_threshold(1).build_named("api").unwrap();
+        record_failure_named("api", 0).unwrap();
+        assert!(!allow_request_named("api", 0).unwrap());
+        assert!(BreakerBuilder::new().with_half_open_max(0).build().is_err());
+    }
+
+    #[test]
+    fn test_probe_available_does_not_consume() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        assert!(probe_available());
+        record_failure(0);
+        assert!(!probe_available());
+        set_half_open_limits(2, 2).unwrap();
+        assert!(allow_request(1_000));
+        assert!(probe_available());
+        assert!(probe_available());
+        assert_eq!(with_default_breaker(|b| b.half_open_calls), 1);
+        assert!(allow_request(1_000));
+        assert!(!probe_available());
+    }
+
+    #[test]
+    fn test_half_open_requires_consecutive_successes() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(3, 3).unwrap();
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_success();
+        record_success();
+        record_failure(1_000);
+        assert_eq!(current_state(), BreakerState::Open);
+
+        assert!(allow_request(2_000));
+        assert_eq!(with_default_breaker(|b| b.success_count), 0);
+        record_success();
+        record_success();
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_cold_start_admits_only_probes() {
+        let _registry = isolated_registry();
+        init_breaker_cold_start(5, 60).unwrap();
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["last_transition_cause"], "cold_start");
+        assert!(allow_request(0));
+        assert!(allow_request(0));
+        assert!(allow_request(0));
+        assert!(!allow_request(0));
+        record_success();
+        record_success();
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert!(allow_request(0));
+    }
+
+    #[test]
+    fn test_load_snapshot_sets_internal_fields() {
+        let _registry = isolated_registry();
+        load_snapshot(
+            r#"{"state": "HalfOpen", "failure_count": 4, "last_failure_time": 1000,
+                "half_open_calls": 2, "half_open_max_calls": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        assert!(allow_request(1_000));
+        assert!(!allow_request(1_000));
+
+        let snapshot = take_snapshot();
+        init_breaker(5, 60);
+        load_snapshot(&snapshot).unwrap();
+        assert_eq!(take_snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_load_snapshot_rejects_invalid() {
+        let _registry = isolated_registry();
+        init_breaker(5, 60);
+        assert!(load_snapshot(r#"{"failure_threshold": 0}"#).is_err());
+        assert!(load_snapshot(r#"{"failure_cuont": 4}"#).is_err());
+        assert!(load_snapshot("not json").is_err());
+        assert_eq!(with_default_breaker(|b| b.failure_threshold), 5);
+    }
+
+    #[test]
+    fn test_fatal_failure_trips_immediately() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(100, 1, 16).unwrap();
+        record_fatal_failure(0);
+        assert_eq!(current_state(), BreakerState::Open);
+        assert!(!allow_request(999));
+        assert!(allow_request(1_000));
+        record_fatal_failure(1_000);
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(current_recovery_timeout_ms(), 2_000);
+    }
+
+    #[test]
+    fn test_probe_interval_spaces_probes() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_probe_interval(500);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        assert!(!allow_request(1_001));
+        assert!(!would_allow(1_499));
+        assert!(allow_request(1_500));
+        assert_eq!(with_default_breaker(|b| b.half_open_calls), 2);
+    }
+
+    #[test]
+    fn test_evaluate_request_reasons() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        let reason = |t| with_default_breaker(|b| b.evaluate_request(t).reason);
+        assert_eq!(reason(0), "closed");
+        record_failure(0);
+        let denied = with_default_breaker(|b| b.evaluate_request(500));
+        assert!(!denied.allowed);
+        assert_eq!((denied.state, denied.reason), ("open", "open_rejected"));
+        assert_eq!(reason(1_000), "half_open_probe");
+        assert_eq!(reason(1_000), "half_open_budget_exhausted");
+        assert_eq!(with_default_breaker(|b| b.short_circuited_count), 2);
+    }
+
+    #[test]
+    fn test_max_open_duration_latches_open() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_max_open_duration(2_500);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure(1_000);
+        assert!(allow_request(2_000));
+        record_failure(2_000);
+        assert!(!allow_request(3_000));
+        assert!(!would_allow(10_000));
+        assert_eq!(time_until_retry(10_000), None);
+        force_half_open(10_000);
+        assert_eq!(current_state(), BreakerState::Open);
+        assert!(with_default_breaker(|b| b.status().latched));
+
+        reset_breaker();
+        assert!(allow_request(10_000));
+        assert!(!with_default_breaker(|b| b.status().latched));
+    }
+
+    #[test]
+    fn test_weighted_failures_trip_on_score() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        record_failure_weighted(1.5, 0).unwrap();
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure_weighted(1.5, 0).unwrap();
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(with_default_breaker(|b| b.failure_count), 2);
+        assert!(record_failure_weighted(-1.0, 0).is_err());
+        assert!(record_failure_weighted(f64::NAN, 0).is_err());
+    }
+
+    #[test]
+    fn test_weighted_failures_expire_from_window() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 60, 10_000).unwrap();
+        record_failure_weighted(2.0, 0).unwrap();
+        record_failure_weighted(0.5, 5_000).unwrap();
+        record_failure(11_000); // The 2.0 failure aged out: 0.5 + 1.0
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert_eq!(with_default_breaker(|b| b.failure_score), 1_500);
+        record_failure_weighted(1.5, 12_000).unwrap();
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_get_config_reports_settings_only() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 20, 30).unwrap();
+        set_half_open_limits(2, 1).unwrap();
+        record_failure(0);
+        let config: serde_json::Value = serde_json::from_str(&get_config()).unwrap();
+        assert_eq!(config["failure_threshold"], 5);
+        assert_eq!(config["recovery_timeout_ms"], 30_000);
+        assert_eq!(config["half_open_max_calls"], 2);
+        assert_eq!(config["half_open_success_threshold"], 1);
+        assert_eq!(config["trip_mode"], "rate");
+        assert_eq!(config["failure_rate_percent"], 50);
+        assert_eq!(config["rate_minimum_calls"], 20);
+        assert!(config.get("failure_count").is_none());
+    }
+
+    #[test]
+    fn test_success_count_tracks_outcome_window() {
+        let _registry = isolated_registry();
+        init_breaker_rate(50, 4, 60).unwrap();
+        for _ in 0..10 {
+            record_success();
+        }
+        assert_eq!(with_default_breaker(|b| b.success_count), 4);
+        record_failure(0); // Evicts a success from the 4-call window
+        assert_eq!(with_default_breaker(|b| b.success_count), 3);
+        record_failure(0);
+        assert_eq!(current_state(), BreakerState::Open);
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["successes"], 0);
+    }
+
+    #[test]
+    fn test_system_health_across_breakers() {
+        let _registry = isolated_registry();
+        create_breaker("db", 1, 1).unwrap();
+        create_breaker("api", 1, 10).unwrap();
+        assert!(system_healthy(0));
+        record_failure_named("db", 0).unwrap();
+        record_failure_named("api", 0).unwrap();
+        assert!(!system_healthy(500));
+        assert_eq!(open_breakers(500), r#"["api","db"]"#);
+        // db is due to probe, so it no longer counts as open
+        assert_eq!(open_breakers(1_000), r#"["api"]"#);
+        assert!(get_status_named("db").unwrap().contains(r#""state":"open""#));
+        assert!(system_healthy(10_000));
+    }
+
+    #[test]
+    fn test_quorum_closes_on_majority() {
+        let _registry = isolated_registry();
+        init_breaker_quorum(1, 1, 3).unwrap();
+        record_failure(0);
+        for _ in 0..3 {
+            assert!(allow_request(1_000));
+        }
+        assert!(!allow_request(1_000));
+        record_failure(1_000);
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        record_success();
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_quorum_reopens_on_minority() {
+        let _registry = isolated_registry();
+        init_breaker_quorum(1, 1, 3).unwrap();
+        record_failure(0);
+        for _ in 0..3 {
+            assert!(allow_request(1_000));
+        }
+        record_failure(1_000);
+        record_failure(1_000); // A majority failed, but one probe is out
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        record_success();
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(with_default_breaker(|b| b.consecutive_trips), 1);
+    }
+
+    #[test]
+    fn test_rapid_requests_at_transition_respect_budget() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 3).unwrap();
+        create_breaker("worker", 1, 1).unwrap();
+        record_failure(0);
+        record_failure_named("worker", 0).unwrap();
+        let granted = (0..5).filter(|_| allow_request(1_000)).count();
+        assert_eq!(granted, 3);
+        let granted = (0..5).filter(|_| allow_request_named("worker", 1_000).unwrap()).count();
+        assert_eq!(granted, 3);
+    }
+
+    #[test]
+    fn test_disabled_breaker_allows_and_keeps_state() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 60_000).unwrap();
+        record_failure(0);
+        assert!(!allow_request(0));
+        set_enabled(false);
+        assert!(allow_request(0));
+        assert!(would_allow(0));
+        record_success();
+        record_failure(10);
+        assert_eq!(with_default_breaker(|b| b.last_failure_time), Some(0));
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["state"], "open");
+        assert_eq!(status["enabled"], false);
+
+        set_enabled(true);
+        assert!(!allow_request(0));
+    }
+
+    #[test]
+    fn test_drain_events_returns_and_clears() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        drain_events();
+        record_failure(5);
+        assert!(allow_request(1_005));
+        let events: serde_json::Value = serde_json::from_str(&drain_events()).unwrap();
+        assert_eq!(
+            events,
+            serde_json::json!([
+                { "from": "closed", "to": "open", "timestamp": 5 },
+                { "from": "open", "to": "half_open", "timestamp": 1_005 },
+            ])
+        );
+        assert_eq!(drain_events(), "[]");
+    }
+
+    #[test]
+    fn test_event_log_drops_oldest() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 0).unwrap();
+        set_event_log_size(2);
+        for t in 0..5 {
+            record_failure(t);
+            allow_request(t);
+        }
+        let events: serde_json::Value = serde_json::from_str(&drain_events()).unwrap();
+        assert_eq!(events.as_array().unwrap().len(), 2);
+        assert_eq!(events[1]["to"], "half_open");
+        assert_eq!(events[1]["timestamp"], 4);
+    }
+
+    #[test]
+    fn test_half_open_traffic_ramp_grows_with_successes() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(1, 10).unwrap();
+        set_half_open_traffic_ramp(10, 20).unwrap();
+        record_failure(0);
+        let admitted = (0..100).filter(|_| allow_request(1_000)).count();
+        assert_eq!(admitted, 10);
+        record_success();
+        record_success(); // Now 50%
+        let admitted = (0..100).filter(|_| allow_request(1_000)).count();
+        assert_eq!(admitted, 50);
+        assert!(allow_request_sampled(1_000, 0.49));
+        assert!(!allow_request_sampled(1_000, 0.5));
+    }
+
+    #[test]
+    fn test_capabilities_report_version_and_modes() {
+        let _registry = isolated_registry();
+        let caps: serde_json::Value = serde_json::from_str(&capabilities()).unwrap();
+        assert_eq!(caps["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(caps["modes"]["rate"], true);
+        assert_eq!(caps["modes"]["date_now"], false);
+        assert!(caps["features"].is_array());
+    }
+
+    #[test]
+    fn test_backoff_resets_after_sustained_success() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 1, 60).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        set_healthy_reset(3, 0);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure(1_000); // Backoff level 1
+        assert!(allow_request(3_000));
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert_eq!(with_default_breaker(|b| b.consecutive_trips), 1);
+
+        record_success();
+        record_success();
+        record_success();
+        with_default_breaker(|b| {
+            assert_eq!(b.consecutive_trips, 0);
+            assert_eq!(b.last_failure_time, None);
+        });
+        record_failure(4_000);
+        assert_eq!(current_recovery_timeout_ms(), 1_000);
+    }
+
+    #[test]
+    fn test_backoff_resets_after_healthy_duration() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 1, 60).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        set_healthy_reset(0, 10_000);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure(1_000);
+        assert!(allow_request(3_000));
+        record_success();
+        record_failure(5_000); // Relapse soon after: still backed off
+        assert_eq!(current_recovery_timeout_ms(), 2_000);
+
+        assert!(allow_request(9_000));
+        record_success();
+        record_failure(20_000); // Closed since 9s, long enough
+        assert_eq!(current_recovery_timeout_ms(), 1_000);
+    }
+
+    #[test]
+    fn test_probe_slots_remaining() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 3).unwrap();
+        assert_eq!(probe_slots_remaining(), 3);
+        record_failure(0);
+        assert_eq!(probe_slots_remaining(), 0);
+        assert!(allow_request(1_000));
+        assert_eq!(probe_slots_remaining(), 2);
+        assert_eq!(probe_slots_remaining(), 2);
+    }
+
+    #[test]
+    fn test_config_grace_discounts_earlier_failures() {
+        let _registry = isolated_registry();
+        init_breaker(10, 60);
+        set_config_grace(5_000);
+        for t in 0..4 {
+            record_failure(t);
+        }
+        configure_breaker(2, 60).unwrap();
+        record_failure(1_000);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(2_000);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_config_grace_with_window_and_expiry() {
+        let _registry = isolated_registry();
+        init_breaker_window(10, 60, 60_000).unwrap();
+        set_config_grace(5_000);
+        record_failure(0);
+        record_failure(1);
+        configure_breaker(3, 60).unwrap();
+        record_failure(1_000);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(6_000); // Grace over: all four failures count
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_status_reports_probe_budget() {
+        let _registry = isolated_registry();
+        init_breaker_full(1, 1, 2).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(summary["half_open_calls"], 0);
+        assert_eq!(summary["half_open_max"], 2);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        assert!(allow_request(1_000));
+        let summary: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(summary["half_open_calls"], 2);
+        assert_eq!(with_default_breaker(|b| b.status().half_open_calls), 2);
+    }
+
+    #[test]
+    fn test_shed_percent_denies_share_while_closed() {
+        let _registry = isolated_registry();
+        init_breaker(5, 60);
+        set_shed_percent(50).unwrap();
+        let allowed = (0..100).filter(|_| allow_request(0)).count();
+        assert_eq!(allowed, 50);
+        assert_eq!(current_state(), BreakerState::Closed);
+        let shed = with_default_breaker(|b| b.evaluate_request_sampled(0, Some(0.2), 1));
+        assert_eq!(shed.reason, "shed");
+        assert!(allow_request_sampled(0, 0.7));
+        set_shed_percent(0).unwrap();
+        assert!((0..10).all(|_| allow_request(0)));
+        assert!(set_shed_percent(101).is_err());
+    }
+
+    #[test]
+    fn test_timeouts_and_errors_reported_separately() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        record_timeout(0);
+        record_failure(1);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_timeout(2);
+        assert_eq!(current_state(), BreakerState::Open);
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["timeout_count"], 2);
+        assert_eq!(status["error_count"], 1);
+    }
+
+    #[test]
+    fn test_export_all_import_all_round_trip() {
+        let _registry = isolated_registry();
+        create_breaker("db", 1, 60).unwrap();
+        record_failure_named("db", 0).unwrap();
+        let exported = export_all();
+
+        with_registry(|breakers| breakers.remove("db"));
+        let mut doc: serde_json::Value = serde_json::from_str(&exported).unwrap();
+        doc["broken"] = serde_json::json!("not a breaker");
+        assert_eq!(import_all(&doc.to_string()), 2);
+        assert!(!allow_request_named("db", 0).unwrap());
+        assert!(allow_request(0));
+        assert_eq!(import_all("[]"), 0);
+    }
+
+    #[test]
+    fn test_ratio_close_at_80_percent() {
+        let _registry = isolated_registry();
+        for (successes, expected) in [(4, BreakerState::Closed), (3, BreakerState::Open)] {
+            init_breaker_ratio_close(5, 80, 1, 1).unwrap();
+            record_failure(0);
+            for _ in 0..5 {
+                assert!(allow_request(1_000));
+            }
+            for probe in 0..5 {
+                if probe < successes {
+                    record_success();
+                } else {
+                    record_failure(1_000);
+                }
+            }
+            assert_eq!(current_state(), expected);
+        }
+        assert!(init_breaker_ratio_close(5, 0, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_try_init_refuses_open_breaker() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        assert!(try_init(2, 60));
+        record_failure(0);
+        record_failure(0);
+        assert!(!try_init(5, 60));
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(with_default_breaker(|b| b.failure_threshold), 2);
+    }
+
+    #[test]
+    fn test_init_reset_is_logged_as_transition() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        record_failure(0);
+        drain_events();
+        init_breaker(1, 60);
+        let events: serde_json::Value = serde_json::from_str(&drain_events()).unwrap();
+        assert_eq!(events[0]["from"], "open");
+        assert_eq!(events[0]["to"], "closed");
+    }
+
+    #[test]
+    fn test_time_unit_micros_matches_millis() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        record_failure(0);
+        assert!(!allow_request(999));
+        assert!(allow_request(1_000));
+
+        set_time_unit("us").unwrap();
+        init_breaker(1, 1);
+        record_failure(0);
+        assert!(!allow_request(999_999));
+        assert_eq!(time_until_retry(500_000), Some(500));
+        assert!(allow_request(1_000_000));
+        assert!(set_time_unit("weeks").is_err());
+
+        set_time_unit("ms").unwrap();
+        init_breaker(1, 60);
+        record_failure(0);
+        assert!(!allow_request(59_999));
+        assert!(allow_request(60_000));
+    }
+
+    #[test]
+    fn test_next_probe_time_follows_backoff() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 1, 60).unwrap();
+        set_half_open_limits(2, 2).unwrap();
+        set_probe_interval(300);
+        assert_eq!(next_probe_time(5), Some(5));
+        record_failure(0);
+        assert_eq!(next_probe_time(100), Some(1_000));
+        assert!(allow_request(1_000));
+        assert_eq!(next_probe_time(1_100), Some(1_300));
+        record_failure(1_100); // Backoff doubles the timeout
+        assert_eq!(next_probe_time(1_100), Some(3_100));
+        assert_eq!(next_probe_time(3_500), Some(3_500));
+    }
+
+    #[test]
+    fn test_state_bytes_round_trip_all_fields() {
+        let _registry = isolated_registry();
+        init_breaker_window(2, 1, 60_000).unwrap();
+        set_trip_categories(vec!["timeout".to_string(), "5xx".to_string()]);
+        set_recovery_jitter(10, 7).unwrap();
+        set_slow_call_threshold(500, 50).unwrap();
+        set_healthy_reset(3, 0);
+        set_shed_percent(5).unwrap();
+        record_result(true, 900, 10);
+        record_timeout(20);
+        record_failure_weighted(1.5, 30).unwrap();
+        guard_begin(40);
+        with_default_breaker(|b| {
+            b.trip_mode = TripMode::Rate { failure_rate_percent: 40, minimum_calls: 7 };
+            b.close_mode = CloseMode::Quorum;
+            b.config_changed_at = Some(25);
+        });
+        let json = export_state();
+        let bytes = export_state_bytes();
+        assert!(bytes.len() < json.len());
+
+        init_breaker(5, 60);
+        assert!(import_state_bytes(&bytes));
+        assert_eq!(export_state(), json);
+    }
+
+    #[test]
+    fn test_state_bytes_reject_bad_input() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        let mut bytes = export_state_bytes();
+        assert!(!import_state_bytes(&bytes[..bytes.len() - 1]));
+        bytes.push(0);
+        assert!(!import_state_bytes(&bytes));
+        bytes.pop();
+        bytes[0] = BINARY_LAYOUT_VERSION + 1;
+        assert!(!import_state_bytes(&bytes));
+        assert!(!import_state_bytes(&[]));
+    }
+
+    #[test]
+    fn test_latency_stats_over_recent_successes() {
+        let _registry = isolated_registry();
+        init_breaker(5, 60);
+        assert_eq!((latency_mean(), latency_p50(), latency_p95()), (0.0, 0, 0));
+
+        // 1..=200 ms: only the last 100 successes (101..=200) count
+        for latency in 1..=200 {
+            record_result(true, latency, latency);
+        }
+        record_result(false, 10_000, 201);
+        assert_eq!(latency_mean(), 150.5);
+        assert_eq!(latency_p50(), 150);
+        assert_eq!(latency_p95(), 195);
+    }
+
+    #[test]
+    fn test_probation_reopens_on_early_relapse() {
+        let _registry = isolated_registry();
+        init_breaker_ms(3, 1000).unwrap();
+        set_probation(5000);
+        let recover = |t: u64| {
+            for i in 0..3 {
+                record_failure(t + i);
+            }
+            assert!(allow_request(t + 1003));
+            for _ in 0..3 {
+                record_success();
+            }
+            assert_eq!(current_state(), BreakerState::Closed);
+        };
+
+        recover(0);
+        record_failure(4000);
+        assert_eq!(current_state(), BreakerState::Open);
+
+        recover(10_000);
+        record_failure(16_005);
+        record_failure(16_006);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(16_007);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[cfg(feature = "shared-state")]
+    #[test]
+    fn test_shared_state_visible_across_threads() {
+        let _registry = isolated_registry();
+        create_breaker("shared", 1, 60).unwrap();
+        std::thread::spawn(|| record_failure_named("shared", 0).unwrap()).join().unwrap();
+        assert!(!allow_request_named("shared", 0).unwrap());
+        assert!(capabilities().contains("shared-state"));
+    }
+
+    #[test]
+    fn test_adaptive_threshold_scales_with_volume() {
+        let _registry = isolated_registry();
+        // Quiet: after one success, 2 failures exceed sqrt(3)
+        init_breaker_adaptive(1.0, 60_000, 60).unwrap();
+        record_result(true, 5, 0);
+        record_failure(1);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(2);
+        assert_eq!(current_state(), BreakerState::Open);
+
+        // Busy: after 100 successes it takes 11 failures to exceed sqrt(100 + n)
+        init_breaker_adaptive(1.0, 60_000, 60).unwrap();
+        for t in 0..100 {
+            record_result(true, 5, t);
+        }
+        for t in 100..110 {
+            record_failure(t);
+        }
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(110);
+        assert_eq!(current_state(), BreakerState::Open);
+
+        assert!(init_breaker_adaptive(0.0, 60_000, 60).is_err());
+        assert!(init_breaker_adaptive(1.0, 0, 60).is_err());
+    }
+
+    // Needs a JS host: run with `wasm-pack test --node`
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test::wasm_bindgen_test]
+    fn test_status_into_sets_properties() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_metadata("owner", "payments").unwrap();
+        record_failure(100);
+        let obj = js_sys::Object::new();
+        get_status_into(&obj).unwrap();
+        let get = |key: &str| js_sys::Reflect::get(&obj, &JsValue::from_str(key)).unwrap();
+        assert_eq!(get("state").as_string().as_deref(), Some("closed"));
+        assert_eq!(get("recovery_timeout").as_f64(), Some(60.0));
+        assert_eq!(get("failure_count").as_f64(), Some(1.0));
+        assert_eq!(get("last_failure_time").as_f64(), Some(100.0));
+        assert_eq!(get("latched").as_bool(), Some(false));
+        assert!(get("state_changed_at").is_null());
+        let owner = js_sys::Reflect::get(&get("metadata"), &JsValue::from_str("owner")).unwrap();
+        assert_eq!(owner.as_string().as_deref(), Some("payments"));
+    }
+
+    #[test]
+    fn test_probe_timeout_frees_abandoned_slots() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(2, 2).unwrap();
+        set_probe_timeout(5_000);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        assert!(allow_request(2_000));
+        assert!(!allow_request(5_999));
+
+        // The first probe times out first; the second is still in flight
+        assert!(allow_request(6_000));
+        assert!(!allow_request(6_500));
+        assert!(allow_request(7_000));
+        assert_eq!(with_default_breaker(|b| b.half_open_calls), 2);
+
+        record_success();
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_metadata_survives_export_import() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        set_metadata("service", "payments").unwrap();
+        set_metadata("region", "eu-west-1").unwrap();
+        let exported = export_state();
+
+        with_default_breaker(|b| *b = CircuitBreakerState::default());
+        assert!(import_state(&exported));
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(
+            status["metadata"],
+            serde_json::json!({"region": "eu-west-1", "service": "payments"})
+        );
+        assert!(import_state_bytes(&export_state_bytes()));
+        assert_eq!(with_default_breaker(|b| b.metadata.len()), 2);
+    }
+
+    #[test]
+    fn test_metadata_is_bounded() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        for i in 0..MAX_METADATA_KEYS {
+            set_metadata(&format!("k{}", i), "v").unwrap();
+        }
+        assert!(set_metadata("one_too_many", "v").is_err());
+        set_metadata("k0", "replaced").unwrap();
+        assert!(set_metadata_named("missing", "k", "v").is_err());
+    }
+
+    #[test]
+    fn test_record_and_status_reflects_transition() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        let status = |json: String| serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(status(record_failure_and_status(0))["state"], "closed");
+        let tripped = status(record_failure_and_status(10));
+        assert_eq!(tripped["state"], "open");
+        assert_eq!(tripped["trip_count"], 1);
+        assert_eq!(status(record_success_and_status())["state"], "open");
+    }
+
+    #[test]
+    fn test_force_open_and_reset_all() {
+        let _registry = isolated_registry();
+        create_breaker("db", 5, 60).unwrap();
+        create_breaker("cache", 5, 60).unwrap();
+        assert_eq!(force_open_all(1_000), 3);
+        assert_eq!(open_breakers(1_000), r#"["cache","db","default"]"#);
+        assert_eq!(reset_all(), 3);
+        assert!(system_healthy(1_000));
+
+        with_registry(|breakers| breakers.clear());
+        assert_eq!(force_open_all(1_000), 0);
+        assert_eq!(reset_all(), 0);
+    }
+
+    #[test]
+    fn test_success_while_open_per_mode() {
+        let _registry = isolated_registry();
+        for (recover, expected) in [(false, BreakerState::Open), (true, BreakerState::HalfOpen)] {
+            init_breaker(1, 60);
+            set_recover_on_open_success(recover);
+            record_failure(0);
+            let before = with_default_breaker(|b| b.total_successes);
+            record_result(true, 5, 100);
+            assert_eq!(current_state(), expected);
+            assert_eq!(with_default_breaker(|b| b.total_successes), before + 1);
+            assert_eq!(with_default_breaker(|b| b.success_count), 0);
+        }
+    }
+
+    #[test]
+    fn test_dwell_times_across_trip_and_recovery() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        assert!(allow_request(500));
+        record_failure(2_000); // Closed 500..2000
+        assert!(allow_request(3_000)); // Open 2000..3000
+        record_result(true, 5, 3_250); // HalfOpen 3000..3250
+        let dwell: serde_json::Value = serde_json::from_str(&dwell_times(4_000)).unwrap();
+        assert_eq!(
+            dwell,
+            serde_json::json!({"closed_ms": 2_250, "open_ms": 1_000, "half_open_ms": 250})
+        );
+
+        reset_metrics();
+        assert_eq!(dwell_times(4_000), r#"{"closed_ms":0,"open_ms":0,"half_open_ms":0}"#);
+    }
+
+    #[test]
+    fn test_recovered_breaker_needs_full_threshold() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 1, 60_000).unwrap();
+        for t in 0..3 {
+            record_failure(t);
+        }
+        assert!(allow_request(1_002));
+        assert_eq!(with_default_breaker(|b| b.failure_count), 0);
+        for _ in 0..3 {
+            record_success();
+        }
+        assert_eq!(current_state(), BreakerState::Closed);
+
+        // The pre-trip failures are still inside the window but no longer count
+        record_failure(1_100);
+        record_failure(1_101);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(1_102);
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_same_seed_same_shedding() {
+        let _registry = isolated_registry();
+        let decisions = |name: &str, seed: u64| {
+            create_breaker(name, 5, 60).unwrap();
+            set_shed_percent_named(name, 50).unwrap();
+            seed_rng_named(name, seed).unwrap();
+            (0..64).map(|t| allow_request_named(name, t).unwrap()).collect::<Vec<_>>()
+        };
+        let run = decisions("a", 7);
+        assert_eq!(run, decisions("b", 7));
+        assert_ne!(run, decisions("c", 8));
+        assert!(run.contains(&true) && run.contains(&false));
+        assert!(set_shed_percent_named("a", 101).is_err());
+        assert!(seed_rng_named("missing", 7).is_err());
+
+        init_breaker(5, 60);
+        set_shed_percent(50).unwrap();
+        seed_rng(7);
+        assert_eq!((0..64).map(allow_request).collect::<Vec<_>>(), run);
+    }
+
+    #[test]
+    fn test_named_variants_target_their_breaker() {
+        let _registry = isolated_registry();
+        init_breaker(5, 60);
+        create_breaker("api", 5, 60).unwrap();
+        init_breaker_window_named("api", 3, 60, 1_000).unwrap();
+        set_slow_start_named("api", 500).unwrap();
+        record_batch_named("api", 2, 3, 100).unwrap();
+        assert_eq!(current_state_named("api").unwrap(), BreakerState::Open);
+        assert!(get_config_named("api").unwrap().contains("\"window_ms\":1000"));
+        assert_eq!(current_state(), BreakerState::Closed);
+        assert!(get_config().contains("\"window_ms\":0"));
+
+        assert!(init_breaker_window_named("api", 0, 60, 1_000).is_err());
+        assert_eq!(
+            record_batch_named("missing", 1, 1, 0),
+            Err(BreakerError::UnknownBreaker("missing".to_string()))
+        );
+        assert!(init_breaker_named("missing", 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_weighted_request_consumes_probe_slots() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(5, 5).unwrap();
+        record_failure(0);
+        assert!(allow_request_weighted(3, 1_000));
+        assert_eq!(probe_slots_remaining(), 2);
+        assert!(!allow_request_weighted(3, 1_000));
+        assert!(allow_request_weighted(0, 1_000));
+        assert_eq!(probe_slots_remaining(), 1);
+
+        record_success(); // Frees the cost-3 probe's slots
+        assert_eq!(probe_slots_remaining(), 4);
+    }
+
+    #[test]
+    fn test_recent_trips_newest_first_and_bounded() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        set_trip_history_size(3);
+        assert_eq!(recent_trips(), "[]");
+        for i in 0..4u64 {
+            let t = i * 10_000;
+            record_failure(t);
+            assert!(allow_request(t + 1_000));
+            record_success();
+        }
+        force_open(50_000);
+        assert_eq!(recent_trips(), "[50000,30000,20000]");
+        set_trip_history_size(1);
+        assert_eq!(recent_trips(), "[50000]");
+    }
+
+    #[test]
+    fn test_gated_recovery_waits_for_confirmation() {
+        let _registry = isolated_registry();
+        init_breaker_gated(1, 1).unwrap();
+        record_failure(0);
+        assert!(!confirm_recovery(500));
+        let reason = |t| with_default_breaker(|b| b.evaluate_request(t).reason);
+        assert_eq!(reason(500), "open_rejected");
+        assert_eq!(reason(60_000), "open_awaiting_confirmation");
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(next_probe_time(60_000), None);
+
+        assert!(confirm_recovery(60_000));
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+        assert!(allow_request(60_001));
+        assert!(!confirm_recovery(60_001));
+    }
+
+    #[test]
+    fn test_force_open_is_sticky() {
+        let _registry = isolated_registry();
+        init_breaker(5, 60);
+        force_open(0);
+        let far_future = 365 * 24 * 3_600_000;
+        assert!(!allow_request(far_future));
+        assert_eq!(current_state(), BreakerState::Open);
+        assert_eq!(with_default_breaker(|b| b.evaluate_request(far_future).reason), "open_forced");
+        assert!(with_default_breaker(|b| b.status().forced_open));
+        assert_eq!(time_until_retry(far_future), None);
+
+        assert!(release_force_open(far_future));
+        assert!(!allow_request(far_future + 1_000));
+        assert!(allow_request(far_future + 60_000));
+        assert!(!release_force_open(far_future + 60_000));
+
+        force_open(0);
+        reset_breaker();
+        assert!(allow_request(far_future));
+    }
+
+    #[test]
+    fn test_evaluate_many_is_read_only() {
+        let _registry = isolated_registry();
+        create_breaker("primary", 1, 1).unwrap();
+        create_breaker("replica", 1, 60).unwrap();
+        record_failure_named("primary", 0).unwrap();
+        let names = vec!["primary".to_string(), "replica".to_string(), "gone".to_string()];
+        let expected = serde_json::json!([
+            {"name": "primary", "allowed": true, "state": "half_open", "error": null},
+            {"name": "replica", "allowed": true, "state": "closed", "error": null},
+            {"name": "gone", "allowed": false, "state": null,
+             "error": "unknown circuit breaker: gone"},
+        ]);
+        for _ in 0..2 {
+            let result: serde_json::Value =
+                serde_json::from_str(&evaluate_many(names.clone(), 1_000)).unwrap();
+            assert_eq!(result, expected);
+        }
+        let state = with_breaker("primary", |b| (b.state, b.half_open_calls)).unwrap();
+        assert_eq!(state, (BreakerState::Open, 0));
+    }
+
+    #[test]
+    fn test_quorum_of_four_majority_and_minority() {
+        let _registry = isolated_registry();
+        for (successes, expected) in [(3, BreakerState::Closed), (1, BreakerState::Open)] {
+            init_breaker_quorum(1, 1, 4).unwrap();
+            record_failure(0);
+            for _ in 0..4 {
+                assert!(allow_request(1_000));
+            }
+            assert!(!allow_request(1_000));
+            for i in 0..4 {
+                if i < successes {
+                    record_success();
+                } else {
+                    record_failure(1_000);
+                }
+            }
+            assert_eq!(current_state(), expected);
+        }
+    }
+
+    #[test]
+    fn test_quorum_timeout_decides_on_reported_probes() {
+        let _registry = isolated_registry();
+        // 3 of 4 required: 2 of 2 reported meets the ratio, 1 of 2 doesn't
+        for (failures, expected) in [(0, BreakerState::Closed), (1, BreakerState::Open)] {
+            // Each run starts later, as the breaker clock never goes back
+            let t = failures * 100_000;
+            init_breaker_quorum(1, 1, 4).unwrap();
+            set_quorum_timeout(5_000);
+            record_failure(t);
+            for _ in 0..4 {
+                assert!(allow_request(t + 1_000));
+            }
+            for i in 0..2 {
+                if i < failures {
+                    record_failure(t + 2_000);
+                } else {
+                    record_success();
+                }
+            }
+            assert!(!allow_request(t + 5_999));
+            assert_eq!(current_state(), BreakerState::HalfOpen);
+            let allowed = allow_request(t + 6_000);
+            assert_eq!(current_state(), expected);
+            assert_eq!(allowed, expected == BreakerState::Closed);
+        }
+    }
+
+    #[test]
+    fn test_flush_drains_all_breakers_once() {
+        let _registry = isolated_registry();
+        init_breaker_ms(1, 1_000).unwrap();
+        create_breaker("db", 1, 60).unwrap();
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure_named("db", 500).unwrap();
+        let flushed: serde_json::Value = serde_json::from_str(&flush()).unwrap();
+        assert_eq!(
+            flushed,
+            serde_json::json!([
+                {"name": "default", "from": "closed", "to": "open", "timestamp": 0},
+                {"name": "db", "from": "closed", "to": "open", "timestamp": 500},
+                {"name": "default", "from": "open", "to": "half_open", "timestamp": 1_000},
+            ])
+        );
+        assert!(with_default_breaker(|b| b.status().quiesced));
+        assert_eq!(flush(), "[]");
+        assert_eq!(drain_events(), "[]");
+    }
+
+    #[test]
+    fn test_last_transition_cause() {
+        let _registry = isolated_registry();
+        let cause = || with_default_breaker(|b| b.status().last_transition_cause);
+        init_breaker_ms(1, 1_000).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+        assert_eq!(cause(), None);
+        record_failure(0);
+        assert_eq!(cause(), Some("failure_threshold"));
+        assert!(allow_request(1_000));
+        assert_eq!(cause(), Some("recovery_timeout"));
+        record_failure(1_100);
+        assert_eq!(cause(), Some("half_open_failure"));
+        force_half_open(1_200);
+        assert_eq!(cause(), Some("manual_force"));
+        record_success();
+        assert_eq!(cause(), Some("probes_succeeded"));
+        force_open(1_300);
+        assert_eq!(cause(), Some("manual_force"));
+        reset_breaker();
+        assert_eq!(cause(), Some("reset"));
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["last_transition_cause"], "reset");
+    }
+
+    #[test]
+    fn test_count_window_forgets_old_outcomes() {
+        let _registry = isolated_registry();
+        init_breaker_count_window(5, 3, 60).unwrap();
+        record_failure(0);
+        record_failure(1);
+        for _ in 0..3 {
+            record_success();
+        }
+        // The window is now [F, F, S, S, S]; each push drops the oldest
+        record_failure(2);
+        assert_eq!(with_default_breaker(|b| b.failure_count), 2);
+        record_failure(3);
+        assert_eq!(current_state(), BreakerState::Closed);
+        record_failure(4);
+        assert_eq!(current_state(), BreakerState::Open);
+
+        assert!(init_breaker_count_window(5, 6, 60).is_err());
+        assert!(init_breaker_count_window(5, 0, 60).is_err());
+    }
+
+    #[test]
+    fn test_reserve_breakers_avoids_growth() {
+        let _registry = isolated_registry();
+        reserve_breakers(200);
+        let capacity = breaker_capacity();
+        assert!(capacity >= 200);
+
+        for i in 0..200 {
+            create_breaker(&format!("svc-{}", i), 3, 30).unwrap();
+        }
+        assert_eq!(breaker_capacity(), capacity);
+
+        // Already large enough: nothing changes
+        reserve_breakers(50);
+        assert_eq!(breaker_capacity(), capacity);
+    }
+
+    #[test]
+    fn test_clock_tolerance_accepts_small_regressions() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        record_failure(10_000);
+        // Slightly behind: counted, at the last failure time
+        record_failure(9_500);
+        with_default_breaker(|b| {
+            assert_eq!(b.failure_count, 2);
+            assert_eq!(b.last_failure_time, Some(10_000));
+        });
+        // Far behind: ignored
+        record_failure(5_000);
+        assert_eq!(with_default_breaker(|b| b.failure_count), 2);
+
+        set_clock_tolerance(0);
+        record_failure(9_999);
+        assert_eq!(with_default_breaker(|b| b.failure_count), 2);
+    }
+
+    #[test]
+    fn test_single_flight_grants_one_probe_at_a_time() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_half_open_limits(3, 3).unwrap();
+        set_single_flight(true);
+        record_failure(0);
+
+        assert!(allow_request(1_000));
+        assert_eq!(with_default_breaker(|b| b.state), BreakerState::HalfOpen);
+        assert!(!allow_request(1_001));
+        let decision = with_default_breaker(|b| b.decide_request(1_002, None, 1));
+        assert_eq!(decision, Decision::HalfOpenProbeInFlight);
+
+        record_success();
+        assert!(allow_request(1_003));
+        assert!(!allow_request(1_004));
+    }
+
+    #[test]
+    fn test_compact_expires_idle_window() {
+        let _registry = isolated_registry();
+        init_breaker_window(5, 60, 10_000).unwrap();
+        record_failure(1_000);
+        record_failure(2_000);
+        let failures = || {
+            let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+            status["failures"].as_u64()
+        };
+        assert_eq!(failures(), Some(2));
+
+        compact(11_500);
+        assert_eq!(failures(), Some(1));
+        compact(20_000);
+        assert_eq!(failures(), Some(0));
+
+        record_failure(30_000);
+        assert!(allow_request(45_000));
+        assert_eq!(failures(), Some(0));
+    }
+
+    #[test]
+    fn test_force_close_allows_requests_immediately() {
+        let _registry = isolated_registry();
+        init_breaker(2, 60);
+        set_metadata("owner", "payments").unwrap();
+        record_failure(1_000);
+        record_failure(1_001);
+        assert!(!allow_request(1_002));
+        let trips = with_default_breaker(|b| b.trip_count);
+
+        force_close(1_003);
+        assert!(allow_request(1_004));
+        with_default_breaker(|b| {
+            assert_eq!(b.state, BreakerState::Closed);
+            assert_eq!(b.failure_count, 0);
+            assert_eq!(b.trip_count, trips);
+            assert_eq!(b.total_failures, 2);
+            assert_eq!(b.failure_threshold, 2);
+            assert_eq!(b.last_transition_cause, Some(TransitionCause::ManualClose));
+            assert_eq!(b.metadata.get("owner").map(String::as_str), Some("payments"));
+        });
+    }
+
+    #[test]
+    fn test_half_open_stall_reopens() {
+        let _registry = isolated_registry();
+        init_breaker(1, 1);
+        set_half_open_limits(2, 2).unwrap();
+        set_half_open_stall(5_000);
+        record_failure(0);
+
+        assert!(allow_request(1_000));
+        assert!(allow_request(1_500));
+        assert!(!allow_request(6_000));
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+
+        assert!(!allow_request(6_500));
+        with_default_breaker(|b| {
+            assert_eq!(b.state, BreakerState::Open);
+            assert_eq!(b.last_failure_time, Some(6_500));
+        });
+    }
+
+    #[test]
+    fn test_evaluations_total_counts_every_request() {
+        let _registry = isolated_registry();
+        init_breaker(2, 1);
+        reset_metrics();
+        for t in 0..3 {
+            allow_request(t);
+        }
+        record_failure(3);
+        record_failure(3);
+        for t in 4..9 {
+            assert!(!allow_request(t));
+        }
+        // Survives the trip and the recovery
+        assert!(allow_request(2_000));
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["evaluations_total"], 9);
+        assert_eq!(status["short_circuited_count"], 5);
+
+        reset_metrics();
+        assert_eq!(with_default_breaker(|b| b.evaluations_total), 0);
+    }
+
+    #[test]
+    fn test_antiflap_penalizes_rapid_cycles() {
+        let _registry = isolated_registry();
+        init_breaker_antiflap(1, 1, 6, 60_000, 30_000).unwrap();
+        set_half_open_limits(1, 1).unwrap();
+
+        // Two trip/recover cycles make six transitions
+        let mut t = 0;
+        for _ in 0..2 {
+            record_failure(t);
+            assert!(allow_request(t + 1_000));
+            record_success();
+            assert_eq!(current_state(), BreakerState::Closed);
+            t += 2_000;
+        }
+
+        // The next trip is within the window: the normal timeout no longer applies
+        record_failure(t);
+        assert!(!allow_request(t + 1_000));
+        assert_eq!(time_until_retry(t + 1_000), Some(29_000));
+        assert!(allow_request(t + 30_000));
+        assert_eq!(current_state(), BreakerState::HalfOpen);
+
+        // The penalty is served; counting starts over
+        record_failure(t + 31_000);
+        assert!(allow_request(t + 32_000));
+
+        assert!(init_breaker_antiflap(1, 1, 1, 60_000, 30_000).is_err());
+    }
+
+    #[test]
+    fn test_state_codes_are_stable() {
+        let _registry = isolated_registry();
+        let states = [BreakerState::Closed, BreakerState::HalfOpen, BreakerState::Open];
+        for (code, state) in states.into_iter().enumerate() {
+            assert_eq!(state.to_code(), code as u8);
+            assert_eq!(BreakerState::from_code(code as u8), Some(state));
+        }
+        assert_eq!(BreakerState::from_code(3), None);
+        assert_eq!(serde_json::to_string(&BreakerState::HalfOpen).unwrap(), r#""HalfOpen""#);
+    }
+
+    #[test]
+    fn test_window_buffer_is_capped() {
+        let _registry = isolated_registry();
+        init_breaker_adaptive(1000.0, 3_600_000, 60).unwrap();
+        set_max_window_entries(50).unwrap();
+        for t in 0..500 {
+            record_failure(t);
+            with_default_breaker(|b| assert!(b.failure_times.len() <= 50));
+        }
+        let status: serde_json::Value = serde_json::from_str(&get_status()).unwrap();
+        assert_eq!(status["window_truncated"], true);
+
+        // Once the dropped entries would have expired the window is exact again
+        compact(3_600_460);
+        assert!(!with_default_breaker(|b| b.window_truncated));
+        assert!(set_max_window_entries(0).is_err());
+    }
+
+    #[test]
+    fn test_force_close_skips_slow_start_and_keeps_backoff() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 1, 60).unwrap();
+        set_slow_start(10_000);
+        set_probation(10_000);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure(1_000);
+        let trips = with_default_breaker(|b| b.consecutive_trips);
+        assert!(trips > 0);
+
+        force_close(1_500);
+        for t in 1_501..1_511 {
+            assert!(allow_request(t));
+        }
+        with_default_breaker(|b| {
+            assert_eq!(b.consecutive_trips, trips);
+            assert!(b.ramp_started_at.is_none());
+            assert!(!b.in_probation());
+        });
+    }
+
+    #[test]
+    fn test_zero_failure_threshold_rejected() {
+        let _registry = isolated_registry();
+        init_breaker(0, 60);
+        assert_eq!(with_default_breaker(|b| b.failure_threshold), 1);
+        init_breaker(2, 60);
+        assert!(init_breaker_ms(0, 1_000).is_err());
+        assert!(configure_breaker(0, 60).is_err());
+        assert!(init_breaker_window(0, 60, 1_000).is_err());
+        assert!(init_breaker_full(0, 60, 1).is_err());
+        assert!(!try_init(0, 60));
+        assert!(create_breaker("db", 0, 60).is_err());
+        assert!(BreakerBuilder::new().with_failure_threshold(0).build().is_err());
+        assert_eq!(with_default_breaker(|b| b.failure_threshold), 2);
+
+        record_success();
+        assert_eq!(current_state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_would_allow_predicts_shedding() {
+        let _registry = isolated_registry();
+        for seeded in [false, true] {
+            init_breaker(5, 60);
+            set_shed_percent(40).unwrap();
+            if seeded {
+                seed_rng(7);
+            }
+            let mut shed = 0;
+            for t in 0..50 {
+                let predicted = would_allow(t);
+                assert_eq!(would_allow(t), predicted, "peeking consumed a draw");
+                assert_eq!(allow_request(t), predicted);
+                shed += u32::from(!predicted);
+            }
+            assert!(shed > 0);
+        }
+    }
+
+    #[test]
+    fn test_would_allow_in_shadow_mode() {
+        let _registry = isolated_registry();
+        init_breaker(1, 60);
+        set_shadow_mode(true);
+        record_failure(0);
+        assert_eq!(current_state(), BreakerState::Open);
+        assert!(would_allow(1));
+        assert!(allow_request(1));
+    }
+
+    #[test]
+    fn test_import_skips_invalid_configs() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        let good = export_state();
+        let doc = format!(
+            r#"{{"ok": {}, "zero": {{"state": "Closed", "failure_threshold": 0}}, "empty": {{}}}}"#,
+            good
+        );
+        assert_eq!(import_all(&doc), 1);
+        assert!(get_status_named("ok").is_ok());
+        assert!(get_status_named("zero").is_err());
+        assert!(get_status_named("empty").is_err());
+
+        assert!(!import_state(r#"{"state": "Open", "failure_threshold": 0}"#));
+        assert!(!import_state("{}"));
+        assert!(import_state(&good));
+    }
+
+    #[test]
+    fn test_ignored_failure_keeps_quorum_budget() {
+        let _registry = isolated_registry();
+        init_breaker_quorum(1, 1, 3).unwrap();
+        set_trip_categories(vec!["5xx".to_string()]);
+        record_failure(0);
+        for t in 1_000..1_003 {
+            assert!(allow_request(t));
+        }
+        record_failure_categorized("404", 1_003);
+        assert!(!allow_request(1_004));
+        with_default_breaker(|b| {
+            assert_eq!(b.half_open_calls, 3);
+            assert_eq!(b.probe_granted_at.len(), 2);
+        });
+    }
+
+
+    #[test]
+    fn test_init_starts_from_default_config() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 1, 100).unwrap();
+        set_shed_percent(50).unwrap();
+        set_metadata("service", "db").unwrap();
+        record_failure(0);
+        init_breaker_backoff(2, 1, 8).unwrap();
+        with_default_breaker(|b| {
+            assert_eq!(b.window_ms, 0);
+            assert_eq!(b.shed_percent, 0);
+            assert!(b.metadata.is_empty());
+            assert_eq!(b.max_recovery_timeout_ms, 8_000);
+            assert_eq!(b.total_failures, 1);
+        });
+        init_breaker(2, 1);
+        with_default_breaker(|b| {
+            let fresh = CircuitBreakerState::new(2, 1_000);
+            assert_eq!(b.max_recovery_timeout_ms, 0);
+            assert_eq!(b.half_open_max_calls, fresh.half_open_max_calls);
+            assert!(b.trip_mode == fresh.trip_mode);
+            assert_eq!(b.total_failures, 1);
+        });
+    }
+
+
+    #[test]
+    fn test_init_after_window_counts_all_failures() {
+        let _registry = isolated_registry();
+        init_breaker_window(3, 1, 100).unwrap();
+        init_breaker(3, 1);
+        for t in [0, 200, 400] {
+            record_failure(t);
+        }
+        assert_eq!(current_state(), BreakerState::Open);
+    }
+
+
+    #[test]
+    fn test_init_after_backoff_uses_fixed_timeout() {
+        let _registry = isolated_registry();
+        init_breaker_backoff(1, 1, 100).unwrap();
+        init_breaker(1, 1);
+        record_failure(0);
+        assert!(allow_request(1_000));
+        record_failure(1_000);
+        assert_eq!(time_until_retry(1_000), Some(1_000));
+    }
+
+    #[test]
+    fn test_init_after_quorum_restores_probe_limit() {
+        let _registry = isolated_registry();
+        init_breaker_quorum(1, 1, 5).unwrap();
+        init_breaker(1, 1);
+        record_failure(0);
+        let granted = (0..5).filter(|_| allow_request(1_000)).count();
+        assert_eq!(granted, CircuitBreakerState::default().half_open_max_calls as usize);
+    }
+
+
+    #[test]
+    fn test_import_bytes_rejects_invalid_config() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        let corrupted = [
+            CircuitBreakerState { failure_threshold: 0, ..Default::default() },
+            CircuitBreakerState { half_open_max_calls: 0, ..Default::default() },
+            CircuitBreakerState {
+                trip_mode: TripMode::Rate { failure_rate_percent: 50, minimum_calls: 0 },
+                ..Default::default()
+            },
+        ];
+        for state in corrupted {
+            assert!(!import_state_bytes(&state.to_bytes()));
+        }
+        assert_eq!(with_default_breaker(|b| b.failure_threshold), 3);
+        assert!(import_state_bytes(&CircuitBreakerState::default().to_bytes()));
+    }
+
+
+    #[test]
+    fn test_reset_clears_latencies_and_credit() {
+        let _registry = isolated_registry();
+        init_breaker(3, 60);
+        set_shed_percent(50).unwrap();
+        record_result(true, 120, 0);
+        allow_request(1);
+        assert_ne!(with_default_breaker(|b| b.shed_credit), 0);
+        reset_breaker();
+        with_default_breaker(|b| {
+            assert_eq!(b.shed_credit, 0);
+            assert!(b.latencies.is_empty());
+            assert_eq!(b.shed_percent, 50);
+        });
+        assert_eq!(latency_mean(), 0.0);
     }
 }